@@ -0,0 +1,324 @@
+use crate::role::{assign_role, forget_roles, ClientRoles, LocalRole, Role, RoleAssignments};
+use crate::{ClientMessage, LekClient, LekServer, ServerMessage, TypeName};
+use bevy_app::App;
+use bevy_ecs::prelude::{EventReader, Events, Local, Res, ResMut, Resource, World};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::server::{ConnectionLostEvent, Endpoint, Server};
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// bump this whenever a message changes shape, clients with another version are turned away
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// how long a rejected client gets to read why before it's disconnected
+const REJECTION_GRACE: Duration = Duration::from_secs(1);
+
+#[derive(Resource, Clone, Debug)]
+pub struct AdmissionSettings {
+    pub max_clients: usize,
+}
+
+impl Default for AdmissionSettings {
+    fn default() -> Self {
+        Self { max_clients: 16 }
+    }
+}
+
+/// identities and addresses that are refused when they connect, identities are self-chosen so only addresses really keep someone out
+#[derive(Resource, Clone, Debug, Default)]
+pub struct BanList {
+    pub identities: HashSet<String>,
+    pub addresses: HashSet<IpAddr>,
+}
+
+impl BanList {
+    pub fn is_banned(&self, identity: &str, address: Option<IpAddr>) -> bool {
+        self.identities.contains(identity) || address.map_or(false, |address| self.addresses.contains(&address))
+    }
+}
+
+/// the remote address of every connected client the transport reported one for
+#[derive(Resource, Default)]
+pub struct PeerAddresses(pub HashMap<ClientId, IpAddr>);
+
+/// where `client_id` connects from, bevy_quinnet 0.4 only logs the remote address so this is the one place to read it
+/// once the endpoint exposes it
+fn peer_address(_endpoint: &Endpoint, _client_id: ClientId) -> Option<IpAddr> {
+    None
+}
+
+/// every client that passed the handshake, with the identity it gave, messages from anyone else are dropped
+#[derive(Resource, Default)]
+pub struct AdmittedClients(pub HashMap<ClientId, String>);
+
+impl AdmittedClients {
+    pub fn contains(&self, client_id: ClientId) -> bool {
+        self.0.contains_key(&client_id)
+    }
+
+    /// the connected clients that passed the handshake, nothing is sent to the others but the handshake
+    pub fn of(&self, endpoint: &Endpoint) -> Vec<ClientId> {
+        endpoint
+            .clients()
+            .into_iter()
+            .filter(|client_id| self.contains(*client_id))
+            .collect()
+    }
+}
+
+/// sent on the server once a client passed the handshake, use it instead of `ConnectionEvent` to greet clients
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAdmitted {
+    pub client_id: ClientId,
+}
+
+/// the name the client identifies itself with, bans and roles are keyed by it
+#[derive(Resource, Clone, Debug)]
+pub struct ClientIdentity(pub String);
+
+/// the role the client asks for when it joins, the server never grants more than it would by default
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct RequestedRole(pub Role);
+
+/// the secret the client proves a role above the server's default with, see `RoleAssignments::tokens`
+#[derive(Resource, Clone, Debug, Default)]
+pub struct RoleToken(pub Option<String>);
+
+impl Default for RequestedRole {
+    fn default() -> Self {
+        Self(Role::Host)
+    }
+}
+
+impl Default for ClientIdentity {
+    fn default() -> Self {
+        let name = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "anonymous".to_string());
+        Self(name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    ServerFull,
+    Banned,
+    /// the server's `PROTOCOL_VERSION`
+    VersionMismatch(u32),
+}
+
+/// sent on the client when the server refused the connection
+#[derive(Clone, Debug)]
+pub struct ConnectionRejected {
+    pub reason: RejectReason,
+}
+
+#[derive(Resource, Default)]
+struct PendingRejections(Vec<(ClientId, Instant)>);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AdmissionMsgServer {
+    /// protocol version, identity, requested role and role token, the first thing a client sends
+    Hello(u32, String, Role, Option<String>),
+}
+
+impl TypeName for AdmissionMsgServer {
+    fn get_type_name() -> String {
+        "leknet::admission::AdmissionMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for AdmissionMsgServer {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        match self {
+            AdmissionMsgServer::Hello(version, identity, requested_role, token) => {
+                if world.resource::<AdmittedClients>().contains(client_id) {
+                    return;
+                }
+                if let Some(address) = peer_address(world.resource::<Server>().endpoint(), client_id) {
+                    world.resource_mut::<PeerAddresses>().0.insert(client_id, address);
+                }
+                match rejection(world, client_id, version, &identity) {
+                    None => {
+                        let role = world
+                            .resource::<RoleAssignments>()
+                            .grant(token.as_deref(), requested_role);
+                        println!("client {} joined as {} ({})", client_id, identity, role);
+                        world
+                            .resource_mut::<AdmittedClients>()
+                            .0
+                            .insert(client_id, identity);
+                        assign_role(world, client_id, role);
+                        world
+                            .resource_mut::<Events<ClientAdmitted>>()
+                            .send(ClientAdmitted { client_id });
+                    }
+                    Some(reason) => {
+                        println!("rejected client {} ({}): {:?}", client_id, identity, reason);
+                        world
+                            .resource_mut::<Server>()
+                            .endpoint_mut()
+                            .send_lek_msg(client_id, AdmissionMsgClient::Rejected(reason))
+                            .unwrap();
+                        world
+                            .resource_mut::<PendingRejections>()
+                            .0
+                            .push((client_id, Instant::now()));
+                    }
+                }
+            }
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        ChannelType::OrderedReliable
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<AdmissionSettings>();
+        app.init_resource::<BanList>();
+        app.init_resource::<AdmittedClients>();
+        app.init_resource::<PeerAddresses>();
+        app.add_event::<ClientAdmitted>();
+        app.init_resource::<RoleAssignments>();
+        app.init_resource::<ClientRoles>();
+        app.add_system(forget_roles);
+        app.init_resource::<PendingRejections>();
+        app.add_system(disconnect_rejected);
+        app.add_system(forget_admitted);
+    }
+}
+
+/// why `client_id` saying hello with `version` and `identity` would be turned away, if it would
+pub(crate) fn rejection(world: &World, client_id: ClientId, version: u32, identity: &str) -> Option<RejectReason> {
+    let address = world.resource::<PeerAddresses>().0.get(&client_id).copied();
+    if version != PROTOCOL_VERSION {
+        Some(RejectReason::VersionMismatch(PROTOCOL_VERSION))
+    } else if world.resource::<BanList>().is_banned(identity, address) {
+        Some(RejectReason::Banned)
+    } else if world.resource::<AdmittedClients>().0.len() >= world.resource::<AdmissionSettings>().max_clients {
+        Some(RejectReason::ServerFull)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AdmissionMsgClient {
+    Rejected(RejectReason),
+    /// sent once admitted, and whenever the role changes afterwards
+    RoleAssigned(Role),
+}
+
+impl TypeName for AdmissionMsgClient {
+    fn get_type_name() -> String {
+        "leknet::admission::AdmissionMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for AdmissionMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            AdmissionMsgClient::Rejected(reason) => {
+                println!("the server refused the connection: {:?}", reason);
+                world
+                    .resource_mut::<Events<ConnectionRejected>>()
+                    .send(ConnectionRejected { reason });
+                world.resource_mut::<Client>().close_all_connections().unwrap();
+            }
+            AdmissionMsgClient::RoleAssigned(role) => {
+                println!("the server made this client a {}", role);
+                world.resource_mut::<LocalRole>().0 = Some(role);
+            }
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        ChannelType::OrderedReliable
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<ClientIdentity>();
+        app.init_resource::<RequestedRole>();
+        app.init_resource::<RoleToken>();
+        app.init_resource::<LocalRole>();
+        app.add_event::<ConnectionRejected>();
+        app.add_system(say_hello);
+    }
+}
+
+/// introduces the client once per connection
+fn say_hello(
+    mut client: ResMut<Client>,
+    identity: Res<ClientIdentity>,
+    requested_role: Res<RequestedRole>,
+    token: Res<RoleToken>,
+    mut local_role: ResMut<LocalRole>,
+    mut said_hello: Local<bool>,
+) {
+    let connection = match client.get_connection_mut() {
+        Some(connection) if connection.is_connected() => connection,
+        _ => {
+            *said_hello = false;
+            local_role.0 = None;
+            return;
+        }
+    };
+    if *said_hello {
+        return;
+    }
+    connection
+        .send_lek_msg(AdmissionMsgServer::Hello(
+            PROTOCOL_VERSION,
+            identity.0.clone(),
+            requested_role.0,
+            token.0.clone(),
+        ))
+        .unwrap();
+    *said_hello = true;
+}
+
+fn disconnect_rejected(mut server: ResMut<Server>, mut pending: ResMut<PendingRejections>) {
+    let endpoint = match server.get_endpoint_mut() {
+        None => return,
+        Some(endpoint) => endpoint,
+    };
+    let clients = endpoint.clients();
+    pending.0.retain(|(client_id, rejected)| {
+        if !clients.contains(client_id) {
+            return false;
+        }
+        if rejected.elapsed() < REJECTION_GRACE {
+            return true;
+        }
+        endpoint.disconnect_client(*client_id).unwrap();
+        false
+    });
+}
+
+fn forget_admitted(
+    mut disconnected: EventReader<ConnectionLostEvent>,
+    mut admitted: ResMut<AdmittedClients>,
+    mut addresses: ResMut<PeerAddresses>,
+) {
+    for client in disconnected.iter() {
+        admitted.0.remove(&client.id);
+        addresses.0.remove(&client.id);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{ClientMessage, LekClient, LekServer, ServerMessage, TypeName};
+use bevy_app::App;
+use bevy_ecs::prelude::{Local, Res, ResMut, Resource, World};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// the server's time base, seconds since it started
+#[derive(Resource)]
+pub struct ServerClock(Instant);
+
+impl Default for ServerClock {
+    fn default() -> Self {
+        Self(Instant::now())
+    }
+}
+
+impl ServerClock {
+    pub fn now(&self) -> f64 {
+        self.0.elapsed().as_secs_f64()
+    }
+}
+
+/// how often clients ping the server to keep `ServerTime` in sync
+#[derive(Resource, Clone, Debug)]
+pub struct ClockSyncSettings {
+    pub interval: Duration,
+}
+
+impl Default for ClockSyncSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+const SAMPLES: usize = 8;
+
+/// the client's estimate of the server's clock
+#[derive(Resource)]
+pub struct ServerTime {
+    local_start: Instant,
+    /// (round trip, offset) of the latest pongs, in seconds
+    samples: VecDeque<(f64, f64)>,
+    offset: Option<f64>,
+    rtt: f64,
+}
+
+impl Default for ServerTime {
+    fn default() -> Self {
+        Self {
+            local_start: Instant::now(),
+            samples: VecDeque::new(),
+            offset: None,
+            rtt: 0.0,
+        }
+    }
+}
+
+impl ServerTime {
+    fn local_now(&self) -> f64 {
+        self.local_start.elapsed().as_secs_f64()
+    }
+
+    /// false until the first pong came back
+    pub fn is_synced(&self) -> bool {
+        self.offset.is_some()
+    }
+
+    /// the server's current time, in the same unit as `ServerClock::now`
+    pub fn now(&self) -> f64 {
+        self.local_now() + self.offset.unwrap_or(0.0)
+    }
+
+    pub fn rtt(&self) -> Duration {
+        Duration::from_secs_f64(self.rtt)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn offset(&self) -> Option<f64> {
+        self.offset
+    }
+
+    fn add_sample(&mut self, sent: f64, server_time: f64) {
+        let received = self.local_now();
+        self.add_sample_at(sent, server_time, received);
+    }
+
+    /// `sent` and `received` are local times, `server_time` is when the server answered
+    pub(crate) fn add_sample_at(&mut self, sent: f64, server_time: f64, received: f64) {
+        let rtt = (received - sent).max(0.0);
+        // the server answered roughly halfway through the round trip
+        let offset = server_time + rtt / 2.0 - received;
+        self.samples.push_back((rtt, offset));
+        while self.samples.len() > SAMPLES {
+            self.samples.pop_front();
+        }
+        // the sample with the shortest round trip had the least queueing delay, so it's the most accurate
+        let (_, best_offset) = self
+            .samples
+            .iter()
+            .copied()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap();
+        self.offset = Some(best_offset);
+        self.rtt = match self.samples.len() {
+            1 => rtt,
+            _ => self.rtt + (rtt - self.rtt) * 0.125,
+        };
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ClockMsgServer {
+    /// local time the ping was sent at
+    Ping(f64),
+}
+
+impl TypeName for ClockMsgServer {
+    fn get_type_name() -> String {
+        "leknet::clock::ClockMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for ClockMsgServer {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        match self {
+            ClockMsgServer::Ping(sent) => {
+                let server_time = world.resource::<ServerClock>().now();
+                world
+                    .resource_mut::<Server>()
+                    .endpoint_mut()
+                    .send_lek_msg(client_id, ClockMsgClient::Pong(sent, server_time))
+                    .unwrap();
+            }
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            ClockMsgServer::Ping(_) => ChannelType::Unreliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<ServerClock>();
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ClockMsgClient {
+    /// the ping's local send time echoed back, and the server's time when it answered
+    Pong(f64, f64),
+}
+
+impl TypeName for ClockMsgClient {
+    fn get_type_name() -> String {
+        "leknet::clock::ClockMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for ClockMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            ClockMsgClient::Pong(sent, server_time) => world
+                .resource_mut::<ServerTime>()
+                .add_sample(sent, server_time),
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            ClockMsgClient::Pong(_, _) => ChannelType::Unreliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<ServerTime>();
+        app.init_resource::<ClockSyncSettings>();
+        app.add_system(ping_server);
+    }
+}
+
+fn ping_server(
+    mut client: ResMut<Client>,
+    server_time: Res<ServerTime>,
+    settings: Res<ClockSyncSettings>,
+    mut last_ping: Local<Option<Instant>>,
+) {
+    // ping quickly until synced so interpolation has a time base right away
+    let interval = match server_time.is_synced() {
+        true => settings.interval,
+        false => Duration::from_millis(100),
+    };
+    if last_ping.map_or(false, |last_ping| last_ping.elapsed() < interval) {
+        return;
+    }
+    if let Some(connection) = client.get_connection_mut() {
+        connection
+            .send_lek_msg(ClockMsgServer::Ping(server_time.local_now()))
+            .unwrap();
+        *last_ping = Some(Instant::now());
+    }
+}
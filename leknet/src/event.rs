@@ -0,0 +1,302 @@
+use crate::{
+    is_client, is_server, listen_server_mut, AdmittedClients, ClientMessage, EntityMap, LekClient, LekServer, NetId, ServerMessage,
+    TypeName,
+};
+use bevy_app::App;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::event::{Event, Events, ManualEventReader};
+use bevy_ecs::prelude::{Mut, Res, ResMut, Resource, World};
+use bevy_ecs::system::SystemState;
+use bevy_quinnet::client::Client;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::any::type_name;
+
+/// a bevy event that is sent to the other peers too
+pub trait NetworkEvent: Event + Clone + Serialize + DeserializeOwned {
+    /// call `map` on every `Entity` the event holds, so it refers to the same entity on the receiving peer
+    fn map_entities(&mut self, _map: &mut dyn FnMut(Entity) -> Entity) {}
+}
+
+/// who a networked event came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventSender {
+    Server,
+    Client(ClientId),
+}
+
+/// which clients a networked event is delivered to, the sender never gets its own event back
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventTarget {
+    All,
+    Only(ClientId),
+    AllExcept(ClientId),
+    /// nobody but the server
+    Server,
+}
+
+impl EventTarget {
+    fn includes(&self, client_id: ClientId) -> bool {
+        match self {
+            EventTarget::All => true,
+            EventTarget::Only(only) => *only == client_id,
+            EventTarget::AllExcept(except) => *except != client_id,
+            EventTarget::Server => false,
+        }
+    }
+}
+
+/// write this instead of a plain `E` to choose who receives it
+#[derive(Clone, Debug)]
+pub struct SendEvent<E> {
+    pub target: EventTarget,
+    pub event: E,
+}
+
+/// every networked `E` that arrived from another peer, with who sent it
+#[derive(Clone, Debug)]
+pub struct ReceivedEvent<E> {
+    pub sender: EventSender,
+    pub event: E,
+}
+
+pub trait NetworkEventAppExt {
+    /// sends every `E` written with `EventWriter<E>` to the other peers, where it shows up in `EventReader<E>`
+    fn add_network_event<E: NetworkEvent>(&mut self) -> &mut Self;
+}
+
+impl NetworkEventAppExt for App {
+    fn add_network_event<E: NetworkEvent>(&mut self) -> &mut Self {
+        let is_client = is_client(self);
+        let is_server = is_server(self);
+        assert!(
+            is_client || is_server,
+            "add LeknetClient or LeknetServer before networking {}",
+            type_name::<E>()
+        );
+        self.add_event::<E>();
+        self.add_event::<SendEvent<E>>();
+        self.add_event::<ReceivedEvent<E>>();
+        self.init_resource::<OutgoingEvents<E>>();
+        if is_client {
+            EventMsgClient::<E>::add_plugin_client(self);
+        }
+        if is_server {
+            EventMsgServer::<E>::add_plugin_server(self);
+        }
+        if let Some(server_app) = listen_server_mut(self) {
+            server_app.add_network_event::<E>();
+        }
+        self
+    }
+}
+
+/// how far the local `E` and `SendEvent<E>` events have been sent
+#[derive(Resource)]
+pub(crate) struct OutgoingEvents<E: Event> {
+    events: ManualEventReader<E>,
+    targeted: ManualEventReader<SendEvent<E>>,
+}
+
+impl<E: Event> Default for OutgoingEvents<E> {
+    fn default() -> Self {
+        Self {
+            events: Default::default(),
+            targeted: Default::default(),
+        }
+    }
+}
+
+/// events written locally since the last call, received ones are skipped by `deliver`
+pub(crate) fn take_outgoing<E: NetworkEvent>(world: &mut World) -> Vec<(EventTarget, E)> {
+    world.resource_scope(|world, mut outgoing: Mut<OutgoingEvents<E>>| {
+        let mut taken: Vec<(EventTarget, E)> = outgoing
+            .events
+            .iter(world.resource::<Events<E>>())
+            .map(|event| (EventTarget::All, event.clone()))
+            .collect();
+        taken.extend(
+            outgoing
+                .targeted
+                .iter(world.resource::<Events<SendEvent<E>>>())
+                .map(|send| (send.target, send.event.clone())),
+        );
+        taken
+    })
+}
+
+/// entities become their `NetId`'s bits on the wire, `None` if one of them isn't networked
+pub(crate) fn to_wire<E: NetworkEvent>(world: &World, mut event: E) -> Option<E> {
+    let entity_map = world.resource::<EntityMap>();
+    let mut mapped = true;
+    event.map_entities(&mut |entity| match entity_map.get_by_left(&entity) {
+        Some(net_id) => Entity::from_bits(net_id.0),
+        None => {
+            mapped = false;
+            entity
+        }
+    });
+    if !mapped {
+        println!("not sending {}, it refers to an entity that isn't networked", type_name::<E>());
+    }
+    mapped.then_some(event)
+}
+
+/// `None` if one of the event's entities isn't known here, e.g. because it was despawned meanwhile
+pub(crate) fn from_wire<E: NetworkEvent>(world: &World, mut event: E) -> Option<E> {
+    let entity_map = world.resource::<EntityMap>();
+    let mut mapped = true;
+    event.map_entities(&mut |entity| match entity_map.get_by_right(&NetId(entity.to_bits())) {
+        Some(entity) => *entity,
+        None => {
+            mapped = false;
+            entity
+        }
+    });
+    if !mapped {
+        println!("dropping {}, it refers to an entity that isn't known here", type_name::<E>());
+    }
+    mapped.then_some(event)
+}
+
+/// hands a received event to the local app without it being sent back out
+pub(crate) fn deliver<E: NetworkEvent>(world: &mut World, sender: EventSender, event: E) {
+    world.resource_mut::<Events<ReceivedEvent<E>>>().send(ReceivedEvent {
+        sender,
+        event: event.clone(),
+    });
+    world.resource_mut::<Events<E>>().send(event);
+    world.resource_scope(|world, mut outgoing: Mut<OutgoingEvents<E>>| {
+        outgoing.events.iter(world.resource::<Events<E>>()).count();
+    });
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EventMsgServer<E>(EventTarget, E);
+
+impl<E: NetworkEvent> TypeName for EventMsgServer<E> {
+    fn get_type_name() -> String {
+        format!("leknet::event::EventMsgServer<{}>", type_name::<E>())
+    }
+}
+
+impl<E: NetworkEvent> ServerMessage for EventMsgServer<E> {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        let EventMsgServer(target, event) = self;
+        // whatever the server wrote itself goes out first so it isn't mistaken for this one
+        flush_server_events::<E>(world);
+        // nobody else can know an entity the server doesn't, so the event isn't relayed either
+        let local_event = match from_wire(world, event.clone()) {
+            None => return,
+            Some(local_event) => local_event,
+        };
+        deliver(world, EventSender::Client(client_id), local_event);
+        let mut system_state: SystemState<(ResMut<Server>, Res<AdmittedClients>)> = SystemState::new(world);
+        let (mut server, admitted) = system_state.get_mut(world);
+        let endpoint = server.endpoint_mut();
+        for client_id2 in admitted.of(endpoint) {
+            if client_id2 == client_id || !target.includes(client_id2) {
+                continue;
+            }
+            endpoint
+                .send_lek_msg(
+                    client_id2,
+                    EventMsgClient(EventSender::Client(client_id), event.clone()),
+                )
+                .unwrap();
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        ChannelType::OrderedReliable
+    }
+
+    fn plugin(app: &mut App) {
+        app.add_system(flush_server_events::<E>);
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EventMsgClient<E>(EventSender, E);
+
+impl<E: NetworkEvent> TypeName for EventMsgClient<E> {
+    fn get_type_name() -> String {
+        format!("leknet::event::EventMsgClient<{}>", type_name::<E>())
+    }
+}
+
+impl<E: NetworkEvent> ClientMessage for EventMsgClient<E> {
+    fn client(self, world: &mut World) {
+        let EventMsgClient(sender, event) = self;
+        flush_client_events::<E>(world);
+        if let Some(event) = from_wire(world, event) {
+            deliver(world, sender, event);
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        ChannelType::OrderedReliable
+    }
+
+    fn plugin(app: &mut App) {
+        app.add_system(flush_client_events::<E>);
+    }
+}
+
+fn flush_server_events<E: NetworkEvent>(world: &mut World) {
+    let outgoing = take_outgoing::<E>(world);
+    if outgoing.is_empty() {
+        return;
+    }
+    let outgoing: Vec<(EventTarget, E)> = outgoing
+        .into_iter()
+        .filter_map(|(target, event)| Some((target, to_wire(world, event)?)))
+        .collect();
+    let mut system_state: SystemState<(ResMut<Server>, Res<AdmittedClients>)> = SystemState::new(world);
+    let (mut server, admitted) = system_state.get_mut(world);
+    let endpoint = match server.get_endpoint_mut() {
+        None => return,
+        Some(endpoint) => endpoint,
+    };
+    for client_id in admitted.of(endpoint) {
+        for (target, event) in outgoing.iter() {
+            if target.includes(client_id) {
+                endpoint
+                    .send_lek_msg(client_id, EventMsgClient(EventSender::Server, event.clone()))
+                    .unwrap();
+            }
+        }
+    }
+}
+
+fn flush_client_events<E: NetworkEvent>(world: &mut World) {
+    let outgoing = take_outgoing::<E>(world);
+    if outgoing.is_empty() {
+        return;
+    }
+    let outgoing: Vec<(EventTarget, E)> = outgoing
+        .into_iter()
+        .filter_map(|(target, event)| Some((target, to_wire(world, event)?)))
+        .collect();
+    let mut client = world.resource_mut::<Client>();
+    if let Some(connection) = client.get_connection_mut() {
+        for (target, event) in outgoing {
+            connection.send_lek_msg(EventMsgServer(target, event)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use crate::{ClientMessage, LekClient, LekServer, ServerMessage, TypeName};
+use bevy_app::App;
+use bevy_ecs::prelude::{Res, ResMut, Resource, World};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// how often peers send heartbeats, and how many may go unanswered before the peer is dropped
+#[derive(Resource, Clone, Debug)]
+pub struct HeartbeatSettings {
+    pub interval: Duration,
+    pub max_missed: u32,
+}
+
+impl Default for HeartbeatSettings {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(250),
+            max_missed: 8,
+        }
+    }
+}
+
+impl HeartbeatSettings {
+    fn timeout(&self) -> Duration {
+        self.interval * self.max_missed
+    }
+}
+
+/// smoothed latency of a connection, on the client this is the link to the server
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct LinkStats {
+    pub rtt: Duration,
+    /// how much the round trip time varies between heartbeats
+    pub jitter: Duration,
+    /// percentage of recent heartbeats that were never answered
+    pub loss: f32,
+}
+
+/// `LinkStats` of every connected client, measured by the server from its own heartbeats
+#[derive(Resource, Default)]
+pub struct ClientLinkStats(pub HashMap<ClientId, LinkStats>);
+
+/// how many recent heartbeats `LinkStats::loss` is computed over
+const LOSS_WINDOW: usize = 64;
+
+pub(crate) struct Heartbeat {
+    next_seq: u32,
+    last_beat: Option<Instant>,
+    in_flight: VecDeque<(u32, Instant)>,
+    /// whether each of the latest heartbeats was answered
+    outcomes: VecDeque<bool>,
+    last_heard: Instant,
+    pub(crate) stats: Option<LinkStats>,
+}
+
+impl Heartbeat {
+    /// every method takes the current time, so tests don't have to wait
+    pub(crate) fn new(now: Instant) -> Self {
+        Self {
+            next_seq: 0,
+            last_beat: None,
+            in_flight: VecDeque::new(),
+            outcomes: VecDeque::new(),
+            last_heard: now,
+            stats: None,
+        }
+    }
+
+    /// the sequence number of the next heartbeat, if one is due
+    pub(crate) fn beat(&mut self, settings: &HeartbeatSettings, now: Instant) -> Option<u32> {
+        while let Some((_, sent)) = self.in_flight.front() {
+            if now.saturating_duration_since(*sent) < settings.timeout() {
+                break;
+            }
+            self.in_flight.pop_front();
+            self.record(false);
+        }
+        if self
+            .last_beat
+            .map_or(false, |last_beat| now.saturating_duration_since(last_beat) < settings.interval)
+        {
+            return None;
+        }
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.last_beat = Some(now);
+        self.in_flight.push_back((seq, now));
+        Some(seq)
+    }
+
+    pub(crate) fn echoed(&mut self, seq: u32, now: Instant) {
+        self.heard(now);
+        let index = match self.in_flight.iter().position(|(seq2, _)| *seq2 == seq) {
+            None => return,
+            Some(index) => index,
+        };
+        let (_, sent) = self.in_flight.remove(index).unwrap();
+        let rtt = now.saturating_duration_since(sent).as_secs_f64();
+        self.stats = Some(match self.stats {
+            None => LinkStats {
+                rtt: Duration::from_secs_f64(rtt),
+                ..Default::default()
+            },
+            Some(mut stats) => {
+                let last_rtt = stats.rtt.as_secs_f64();
+                let jitter = stats.jitter.as_secs_f64();
+                // same smoothing factors as rfc 6298 and rfc 3550
+                stats.rtt = Duration::from_secs_f64(last_rtt + (rtt - last_rtt) * 0.125);
+                stats.jitter = Duration::from_secs_f64(jitter + ((rtt - last_rtt).abs() - jitter) * 0.0625);
+                stats
+            }
+        });
+        self.record(true);
+    }
+
+    pub(crate) fn heard(&mut self, now: Instant) {
+        self.last_heard = now;
+    }
+
+    fn record(&mut self, answered: bool) {
+        self.outcomes.push_back(answered);
+        while self.outcomes.len() > LOSS_WINDOW {
+            self.outcomes.pop_front();
+        }
+        if let Some(stats) = &mut self.stats {
+            let lost = self.outcomes.iter().filter(|answered| !**answered).count();
+            stats.loss = lost as f32 / self.outcomes.len() as f32 * 100.0;
+        }
+    }
+
+    pub(crate) fn timed_out(&self, settings: &HeartbeatSettings, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_heard) > settings.timeout()
+    }
+}
+
+#[derive(Resource, Default)]
+struct ClientHeartbeats(HashMap<ClientId, Heartbeat>);
+
+#[derive(Resource)]
+struct ServerHeartbeat(Heartbeat);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum HeartbeatMsgServer {
+    Beat(u32),
+    Echo(u32),
+}
+
+impl TypeName for HeartbeatMsgServer {
+    fn get_type_name() -> String {
+        "leknet::heartbeat::HeartbeatMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for HeartbeatMsgServer {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        let mut heartbeats = world.resource_mut::<ClientHeartbeats>();
+        let heartbeat = heartbeats
+            .0
+            .entry(client_id)
+            .or_insert_with(|| Heartbeat::new(Instant::now()));
+        match self {
+            HeartbeatMsgServer::Beat(seq) => {
+                heartbeat.heard(Instant::now());
+                world
+                    .resource_mut::<Server>()
+                    .endpoint_mut()
+                    .send_lek_msg(client_id, HeartbeatMsgClient::Echo(seq))
+                    .unwrap();
+            }
+            HeartbeatMsgServer::Echo(seq) => {
+                heartbeat.echoed(seq, Instant::now());
+                let stats = heartbeat.stats;
+                if let Some(stats) = stats {
+                    world
+                        .resource_mut::<ClientLinkStats>()
+                        .0
+                        .insert(client_id, stats);
+                }
+            }
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        ChannelType::Unreliable
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<HeartbeatSettings>();
+        app.init_resource::<ClientHeartbeats>();
+        app.init_resource::<ClientLinkStats>();
+        app.add_system(beat_clients);
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum HeartbeatMsgClient {
+    Beat(u32),
+    Echo(u32),
+}
+
+impl TypeName for HeartbeatMsgClient {
+    fn get_type_name() -> String {
+        "leknet::heartbeat::HeartbeatMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for HeartbeatMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            HeartbeatMsgClient::Beat(seq) => {
+                world.resource_mut::<ServerHeartbeat>().0.heard(Instant::now());
+                if let Some(connection) = world.resource_mut::<Client>().get_connection_mut() {
+                    connection.send_lek_msg(HeartbeatMsgServer::Echo(seq)).unwrap();
+                }
+            }
+            HeartbeatMsgClient::Echo(seq) => {
+                let mut heartbeat = world.resource_mut::<ServerHeartbeat>();
+                heartbeat.0.echoed(seq, Instant::now());
+                let stats = heartbeat.0.stats;
+                if let Some(stats) = stats {
+                    *world.resource_mut::<LinkStats>() = stats;
+                }
+            }
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        ChannelType::Unreliable
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<HeartbeatSettings>();
+        app.insert_resource(ServerHeartbeat(Heartbeat::new(Instant::now())));
+        app.init_resource::<LinkStats>();
+        app.add_system(beat_server);
+    }
+}
+
+fn beat_clients(
+    mut server: ResMut<Server>,
+    settings: Res<HeartbeatSettings>,
+    mut heartbeats: ResMut<ClientHeartbeats>,
+    mut link_stats: ResMut<ClientLinkStats>,
+) {
+    let endpoint = match server.get_endpoint_mut() {
+        None => return,
+        Some(endpoint) => endpoint,
+    };
+    let now = Instant::now();
+    let clients = endpoint.clients();
+    heartbeats.0.retain(|client_id, _| clients.contains(client_id));
+    link_stats.0.retain(|client_id, _| clients.contains(client_id));
+    for client_id in clients {
+        let heartbeat = heartbeats
+            .0
+            .entry(client_id)
+            .or_insert_with(|| Heartbeat::new(now));
+        if heartbeat.timed_out(&settings, now) {
+            println!("client {} missed {} heartbeats, disconnecting it", client_id, settings.max_missed);
+            heartbeats.0.remove(&client_id);
+            link_stats.0.remove(&client_id);
+            endpoint.disconnect_client(client_id).unwrap();
+            continue;
+        }
+        if let Some(seq) = heartbeat.beat(&settings, now) {
+            endpoint
+                .send_lek_msg(client_id, HeartbeatMsgClient::Beat(seq))
+                .unwrap();
+        }
+    }
+}
+
+fn beat_server(
+    mut client: ResMut<Client>,
+    settings: Res<HeartbeatSettings>,
+    mut heartbeat: ResMut<ServerHeartbeat>,
+    mut link_stats: ResMut<LinkStats>,
+) {
+    let now = Instant::now();
+    let connection = match client.get_connection_mut() {
+        Some(connection) if connection.is_connected() => connection,
+        _ => {
+            // start over once a connection is made
+            heartbeat.0 = Heartbeat::new(now);
+            return;
+        }
+    };
+    if heartbeat.0.timed_out(&settings, now) {
+        println!("the server missed {} heartbeats, disconnecting", settings.max_missed);
+        *link_stats = LinkStats::default();
+        client.close_all_connections().unwrap();
+        return;
+    }
+    if let Some(seq) = heartbeat.0.beat(&settings, now) {
+        connection.send_lek_msg(HeartbeatMsgServer::Beat(seq)).unwrap();
+    }
+}
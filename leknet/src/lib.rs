@@ -1,10 +1,15 @@
+pub mod admission;
+pub mod clock;
+pub mod event;
+pub mod heartbeat;
+pub mod role;
 #[cfg(test)]
 mod test;
 
-use bevy_app::{App, Plugin};
+use bevy_app::{App, AppLabel, Plugin};
 use bevy_ecs::entity::Entity;
 use bevy_ecs::event::{EventReader, EventWriter};
-use bevy_ecs::prelude::{Component, ResMut, Resource};
+use bevy_ecs::prelude::{Component, Res, ResMut, Resource};
 use bevy_ecs::system::SystemState;
 use bevy_ecs::world::World;
 use bevy_quinnet::client::certificate::CertificateVerificationMode;
@@ -21,7 +26,16 @@ use serde::{Deserialize, Serialize};
 use std::any::Any;
 use std::collections::HashMap;
 use std::net::{SocketAddr, SocketAddrV4};
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, Range};
+
+pub use admission::{
+    AdmissionSettings, AdmittedClients, BanList, ClientAdmitted, ClientIdentity, ConnectionRejected, PeerAddresses,
+    RejectReason, RequestedRole, RoleToken, PROTOCOL_VERSION,
+};
+pub use clock::{ClockSyncSettings, ServerClock, ServerTime};
+pub use event::{EventSender, EventTarget, NetworkEvent, NetworkEventAppExt, ReceivedEvent, SendEvent};
+pub use heartbeat::{ClientLinkStats, HeartbeatSettings, LinkStats};
+pub use role::{assign_role, ClientRoles, LocalRole, Role, RoleAssignments};
 
 #[derive(Resource)]
 pub struct ServerMessageMap(
@@ -29,19 +43,45 @@ pub struct ServerMessageMap(
 );
 #[derive(Resource)]
 pub struct ClientMessageMap(pub HashMap<String, Box<(dyn Fn(&mut World, &[u8]) + Sync + Send)>>);
+/// maps the local `Entity` of every networked entity to its `NetId`, on both the server and the clients
 #[derive(Resource)]
-pub struct EntityMap(pub BiHashMap<ClientEntity, ServerEntity>);
+pub struct EntityMap(pub BiHashMap<Entity, NetId>);
 
 #[derive(Component)]
 pub struct Networked;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
-pub struct ClientEntity(pub Entity);
-#[derive(Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
-pub struct ServerEntity(pub Entity);
+/// stable network identity of an entity, allocated by the server or from a range it granted, and shared by every peer
+#[derive(Component, Copy, Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct NetId(pub u64);
+
+/// hands out `NetId`s on the server
+#[derive(Resource, Default)]
+pub struct NetIdAllocator {
+    next: u64,
+}
+
+impl NetIdAllocator {
+    pub fn allocate(&mut self) -> NetId {
+        let net_id = NetId(self.next);
+        self.next += 1;
+        net_id
+    }
+
+    /// hands out `len` consecutive ids at once, for a client to assign itself
+    pub fn allocate_range(&mut self, len: u64) -> Range<u64> {
+        let range = self.next..self.next + len;
+        self.next += len;
+        range
+    }
+
+    /// makes sure `net_id` is never handed out, e.g. because it was restored from disk
+    pub fn reserve(&mut self, net_id: NetId) {
+        self.next = self.next.max(net_id.0 + 1);
+    }
+}
 
 impl Deref for EntityMap {
-    type Target = BiHashMap<ClientEntity, ServerEntity>;
+    type Target = BiHashMap<Entity, NetId>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -149,9 +189,13 @@ pub struct LeknetClient;
 impl Plugin for LeknetServer {
     fn build(&self, app: &mut App) {
         app.insert_resource(EntityMap(BiHashMap::new()));
+        app.insert_resource(NetIdAllocator::default());
         app.insert_resource(ServerMessageMap(HashMap::new()));
         app.add_system(server_msg);
         app.add_event::<ServerMsg>();
+        admission::AdmissionMsgServer::add_plugin_server(app);
+        clock::ClockMsgServer::add_plugin_server(app);
+        heartbeat::HeartbeatMsgServer::add_plugin_server(app);
     }
 }
 
@@ -161,9 +205,31 @@ impl Plugin for LeknetClient {
         app.insert_resource(ClientMessageMap(HashMap::new()));
         app.add_system(client_msg);
         app.add_event::<ClientMsg>();
+        admission::AdmissionMsgClient::add_plugin_client(app);
+        clock::ClockMsgClient::add_plugin_client(app);
+        heartbeat::HeartbeatMsgClient::add_plugin_client(app);
     }
 }
 
+/// true once `LeknetServer` has been added to the app
+pub fn is_server(app: &App) -> bool {
+    app.world.contains_resource::<ServerMessageMap>()
+}
+
+/// true once `LeknetClient` has been added to the app
+pub fn is_client(app: &App) -> bool {
+    app.world.contains_resource::<ClientMessageMap>()
+}
+
+/// label of the server `App` running inside a client `App` that hosts the session itself
+#[derive(AppLabel, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ListenServerApp;
+
+/// the listen server running inside `app`, if it hosts one
+pub fn listen_server_mut(app: &mut App) -> Option<&mut App> {
+    app.get_sub_app_mut(ListenServerApp).ok()
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message {
     name: String,
@@ -198,8 +264,14 @@ fn server_msg(world: &mut World) {
         }
     }
 
+    let hello = admission::AdmissionMsgServer::get_type_name();
     for msg in messages {
         match msg {
+            ServerMsg(name, _, client_id)
+                if name != hello && !world.resource::<AdmittedClients>().contains(client_id) =>
+            {
+                continue;
+            }
             ServerMsg(name, data, client_id) => {
                 let mut system_state: SystemState<ResMut<ServerMessageMap>> =
                     SystemState::new(world);
@@ -256,10 +328,11 @@ fn client_msg(world: &mut World) {
 }
 
 #[allow(dead_code)]
-pub fn start_server(mut server: ResMut<Server>) {
+pub fn start_server(mut server: ResMut<Server>, bind_addr: Option<Res<ServerBindAddr>>) {
+    let bind_addr = bind_addr.map_or_else(ServerBindAddr::default, |bind_addr| *bind_addr);
     server
         .start_endpoint(
-            ServerConfiguration::from_addr(server_addr()),
+            ServerConfiguration::from_addr(bind_addr.0),
             CertificateRetrievalMode::GenerateSelfSigned {
                 server_hostname: "myserver".to_string(),
             },
@@ -283,6 +356,16 @@ pub fn connect_to_server(mut client: ResMut<Client>) {
 
 pub const SERVER_ADDR: &'static str = "127.0.0.1:5000";
 
+/// the address `start_server` listens on, every interface by default so other machines can join
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ServerBindAddr(pub SocketAddr);
+
+impl Default for ServerBindAddr {
+    fn default() -> Self {
+        Self(SocketAddr::V4(SocketAddrV4::new("0.0.0.0".parse().unwrap(), server_addr().port())))
+    }
+}
+
 pub fn server_addr() -> SocketAddr {
     SERVER_ADDR.parse().unwrap()
 }
@@ -0,0 +1,110 @@
+use crate::admission::AdmissionMsgClient;
+use crate::LekServer;
+use bevy_ecs::prelude::{EventReader, ResMut, Resource, World};
+use bevy_quinnet::server::{ConnectionLostEvent, Server};
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// what a client is allowed to do, ordered from least to most privileged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Role {
+    Spectator,
+    Guest,
+    Member,
+    Moderator,
+    Host,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spectator" => Ok(Role::Spectator),
+            "guest" => Ok(Role::Guest),
+            "member" => Ok(Role::Member),
+            "moderator" => Ok(Role::Moderator),
+            "host" => Ok(Role::Host),
+            _ => Err(format!("unknown role {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Spectator => "spectator",
+            Role::Guest => "guest",
+            Role::Member => "member",
+            Role::Moderator => "moderator",
+            Role::Host => "host",
+        };
+        f.write_str(name)
+    }
+}
+
+/// the role a client gets when it's admitted, identities are self-chosen so only a secret token grants more than `default`
+#[derive(Resource, Clone, Debug)]
+pub struct RoleAssignments {
+    pub default: Role,
+    /// secret tokens handed out of band and the role each one grants
+    pub tokens: HashMap<String, Role>,
+}
+
+impl Default for RoleAssignments {
+    fn default() -> Self {
+        Self {
+            default: Role::Member,
+            tokens: HashMap::new(),
+        }
+    }
+}
+
+impl RoleAssignments {
+    /// the role for a client that presented `token`, never more than it asked for
+    pub fn grant(&self, token: Option<&str>, requested: Role) -> Role {
+        token
+            .and_then(|token| self.tokens.get(token))
+            .map_or(self.default, |role| (*role).max(self.default))
+            .min(requested)
+    }
+}
+
+/// role of every admitted client
+#[derive(Resource, Default)]
+pub struct ClientRoles(pub HashMap<ClientId, Role>);
+
+impl ClientRoles {
+    /// clients that haven't been admitted have no rights at all
+    pub fn get(&self, client_id: ClientId) -> Role {
+        self.0.get(&client_id).copied().unwrap_or(Role::Spectator)
+    }
+}
+
+/// the client's own role, `None` until the server admitted it
+#[derive(Resource, Default)]
+pub struct LocalRole(pub Option<Role>);
+
+/// changes `client_id`'s role and tells it
+pub fn assign_role(world: &mut World, client_id: ClientId, role: Role) {
+    world.resource_mut::<ClientRoles>().0.insert(client_id, role);
+    let mut server = world.resource_mut::<Server>();
+    let endpoint = server.endpoint_mut();
+    if endpoint.clients().contains(&client_id) {
+        endpoint
+            .send_lek_msg(client_id, AdmissionMsgClient::RoleAssigned(role))
+            .unwrap();
+    }
+}
+
+pub(crate) fn forget_roles(
+    mut disconnected: EventReader<ConnectionLostEvent>,
+    mut roles: ResMut<ClientRoles>,
+) {
+    for client in disconnected.iter() {
+        roles.0.remove(&client.id);
+    }
+}
@@ -113,3 +113,190 @@ fn my_system(mut client: ResMut<Client>) {
 
 #[test]
 pub fn test_ports() {}
+
+#[test]
+fn server_time_keeps_the_fastest_sample() {
+    let mut server_time = crate::ServerTime::default();
+    assert!(!server_time.is_synced());
+    server_time.add_sample_at(0.0, 10.0, 0.2);
+    assert!(server_time.is_synced());
+    assert!((server_time.offset().unwrap() - 9.9).abs() < 1e-9);
+    // a quicker round trip had less queueing delay, so its offset wins
+    server_time.add_sample_at(1.0, 11.5, 1.04);
+    assert!((server_time.offset().unwrap() - 10.48).abs() < 1e-9);
+    // a slow one doesn't replace it, but still moves the round trip estimate
+    server_time.add_sample_at(2.0, 12.0, 2.5);
+    assert!((server_time.offset().unwrap() - 10.48).abs() < 1e-9);
+    assert!(server_time.rtt() > std::time::Duration::from_secs_f64(0.2));
+    // once it falls out of the window the best remaining sample is used
+    for i in 0..8 {
+        let sent = 3.0 + i as f64;
+        server_time.add_sample_at(sent, sent + 10.0, sent + 0.1);
+    }
+    assert!((server_time.offset().unwrap() - 9.95).abs() < 1e-9);
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Poke(bevy_ecs::entity::Entity);
+
+impl crate::NetworkEvent for Poke {
+    fn map_entities(&mut self, map: &mut dyn FnMut(bevy_ecs::entity::Entity) -> bevy_ecs::entity::Entity) {
+        self.0 = map(self.0);
+    }
+}
+
+fn event_world() -> World {
+    use crate::event::{OutgoingEvents, ReceivedEvent, SendEvent};
+    use bevy_ecs::event::Events;
+
+    let mut world = World::new();
+    world.insert_resource(crate::EntityMap(bimap::BiHashMap::new()));
+    world.init_resource::<Events<Poke>>();
+    world.init_resource::<Events<SendEvent<Poke>>>();
+    world.init_resource::<Events<ReceivedEvent<Poke>>>();
+    world.init_resource::<OutgoingEvents<Poke>>();
+    world
+}
+
+#[test]
+fn network_event_round_trip() {
+    use crate::event::{deliver, from_wire, take_outgoing, to_wire};
+    use crate::{EventSender, EventTarget, NetId, ReceivedEvent};
+    use bevy_ecs::event::Events;
+
+    let mut client = event_world();
+    let client_entity = client.spawn_empty().id();
+    client.resource_mut::<crate::EntityMap>().insert(client_entity, NetId(7));
+    let mut server = event_world();
+    // spawn something first so the two worlds don't happen to agree on the entity
+    server.spawn_empty();
+    let server_entity = server.spawn_empty().id();
+    server.resource_mut::<crate::EntityMap>().insert(server_entity, NetId(7));
+    assert_ne!(client_entity, server_entity);
+
+    client.resource_mut::<Events<Poke>>().send(Poke(client_entity));
+    let outgoing = take_outgoing::<Poke>(&mut client);
+    assert_eq!(outgoing.len(), 1);
+    let (target, event) = outgoing.into_iter().next().unwrap();
+    assert_eq!(target, EventTarget::All);
+    let wire = to_wire(&client, event).unwrap();
+    let wire: Poke = bincode::deserialize(&bincode::serialize(&wire).unwrap()).unwrap();
+
+    let received = from_wire(&server, wire).unwrap();
+    assert_eq!(received, Poke(server_entity));
+    deliver(&mut server, EventSender::Client(3), received);
+    let mut reader = server.resource::<Events<ReceivedEvent<Poke>>>().get_reader();
+    let delivered: Vec<_> = reader.iter(server.resource::<Events<ReceivedEvent<Poke>>>()).cloned().collect();
+    assert_eq!(delivered.len(), 1);
+    assert_eq!(delivered[0].sender, EventSender::Client(3));
+    assert_eq!(delivered[0].event, Poke(server_entity));
+    // it shows up as a plain event too, but isn't sent back out
+    assert_eq!(server.resource::<Events<Poke>>().len(), 1);
+    assert!(take_outgoing::<Poke>(&mut server).is_empty());
+
+    // events about entities the other side can't know are dropped instead of pointing at a placeholder
+    let local_only = client.spawn_empty().id();
+    assert!(to_wire(&client, Poke(local_only)).is_none());
+    let unknown = Poke(bevy_ecs::entity::Entity::from_bits(NetId(8).0));
+    assert!(from_wire(&server, unknown).is_none());
+}
+
+#[test]
+fn heartbeat_stats_and_timeout() {
+    use crate::heartbeat::Heartbeat;
+    use crate::HeartbeatSettings;
+    use std::time::{Duration, Instant};
+
+    let settings = HeartbeatSettings {
+        interval: Duration::from_millis(250),
+        max_missed: 8,
+    };
+    let start = Instant::now();
+    let at = |millis: u64| start + Duration::from_millis(millis);
+    let mut heartbeat = Heartbeat::new(at(0));
+
+    assert_eq!(heartbeat.beat(&settings, at(0)), Some(0));
+    assert_eq!(heartbeat.beat(&settings, at(100)), None);
+    assert_eq!(heartbeat.beat(&settings, at(250)), Some(1));
+    assert!(heartbeat.stats.is_none());
+
+    heartbeat.echoed(0, at(60));
+    let stats = heartbeat.stats.unwrap();
+    assert_eq!(stats.rtt, Duration::from_millis(60));
+    assert_eq!(stats.loss, 0.0);
+    heartbeat.echoed(1, at(350));
+    let stats = heartbeat.stats.unwrap();
+    // smoothed towards the 100ms sample, the 40ms difference counts as jitter
+    assert!((stats.rtt.as_secs_f64() - 0.065).abs() < 1e-6);
+    assert!((stats.jitter.as_secs_f64() - 0.0025).abs() < 1e-6);
+    // echoes of heartbeats that aren't in flight change nothing
+    heartbeat.echoed(1, at(400));
+    heartbeat.echoed(99, at(400));
+    assert!((heartbeat.stats.unwrap().rtt.as_secs_f64() - 0.065).abs() < 1e-6);
+
+    // a heartbeat that goes unanswered for the whole timeout counts as lost
+    assert_eq!(heartbeat.beat(&settings, at(500)), Some(2));
+    assert_eq!(heartbeat.beat(&settings, at(2600)), Some(3));
+    assert!((heartbeat.stats.unwrap().loss - 100.0 / 3.0).abs() < 1e-4);
+
+    // the peer was last heard from at 400ms
+    assert!(!heartbeat.timed_out(&settings, at(2400)));
+    assert!(heartbeat.timed_out(&settings, at(2401)));
+    heartbeat.heard(at(2401));
+    assert!(!heartbeat.timed_out(&settings, at(2401)));
+}
+
+#[test]
+fn admission_rejection_reasons() {
+    use crate::admission::rejection;
+    use crate::{AdmissionSettings, AdmittedClients, BanList, PeerAddresses, RejectReason, PROTOCOL_VERSION};
+    use std::net::IpAddr;
+
+    let mut world = World::new();
+    world.insert_resource(AdmissionSettings { max_clients: 1 });
+    world.init_resource::<BanList>();
+    world.init_resource::<AdmittedClients>();
+    world.init_resource::<PeerAddresses>();
+    world.resource_mut::<BanList>().identities.insert("mallory".to_string());
+    let banned: IpAddr = "203.0.113.7".parse().unwrap();
+    world.resource_mut::<BanList>().addresses.insert(banned);
+
+    assert_eq!(rejection(&world, 2, PROTOCOL_VERSION, "alice"), None);
+    assert_eq!(
+        rejection(&world, 2, PROTOCOL_VERSION + 1, "alice"),
+        Some(RejectReason::VersionMismatch(PROTOCOL_VERSION))
+    );
+    assert_eq!(rejection(&world, 2, PROTOCOL_VERSION, "mallory"), Some(RejectReason::Banned));
+    // a new name doesn't help from a banned address
+    world.resource_mut::<PeerAddresses>().0.insert(3, banned);
+    assert_eq!(rejection(&world, 3, PROTOCOL_VERSION, "not mallory"), Some(RejectReason::Banned));
+    world.resource_mut::<PeerAddresses>().0.insert(4, "198.51.100.1".parse().unwrap());
+    assert_eq!(rejection(&world, 4, PROTOCOL_VERSION, "alice"), None);
+
+    world.resource_mut::<AdmittedClients>().0.insert(1, "bob".to_string());
+    assert_eq!(rejection(&world, 2, PROTOCOL_VERSION, "alice"), Some(RejectReason::ServerFull));
+    // the version is checked before anything else, so old clients learn why they can't join
+    assert_eq!(
+        rejection(&world, 2, PROTOCOL_VERSION + 1, "mallory"),
+        Some(RejectReason::VersionMismatch(PROTOCOL_VERSION))
+    );
+}
+
+#[test]
+fn role_grant_needs_a_token_and_respects_the_request() {
+    use crate::{Role, RoleAssignments};
+
+    let mut assignments = RoleAssignments::default();
+    assignments.tokens.insert("secret".to_string(), Role::Host);
+    assignments.tokens.insert("demoted".to_string(), Role::Spectator);
+
+    // asking for host isn't enough, whatever the identity
+    assert_eq!(assignments.grant(None, Role::Host), Role::Member);
+    assert_eq!(assignments.grant(Some("guess"), Role::Host), Role::Member);
+    assert_eq!(assignments.grant(Some("secret"), Role::Host), Role::Host);
+    // a client never gets more than it asked for
+    assert_eq!(assignments.grant(Some("secret"), Role::Moderator), Role::Moderator);
+    assert_eq!(assignments.grant(None, Role::Spectator), Role::Spectator);
+    // a token can't take away the default role
+    assert_eq!(assignments.grant(Some("demoted"), Role::Host), Role::Member);
+}
@@ -0,0 +1,54 @@
+use bevy_app::App;
+use bevy_ecs::prelude::{Events, World};
+use bevy_quinnet::shared::channel::ChannelType;
+use leknet::{ClientMessage, TypeName};
+use serde::{Deserialize, Serialize};
+
+/// a message the server's admin broadcast to everyone
+#[derive(Clone, Debug)]
+pub struct ServerNotice(pub String);
+
+/// what an `AdminMsgServer::Command` this client sent did
+#[derive(Clone, Debug)]
+pub struct AdminOutput(pub String);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AdminMsgClient {
+    Output(String),
+    Notice(String),
+}
+
+impl TypeName for AdminMsgClient {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::AdminMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for AdminMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            AdminMsgClient::Output(output) => {
+                world.resource_mut::<Events<AdminOutput>>().send(AdminOutput(output));
+            }
+            AdminMsgClient::Notice(text) => {
+                println!("server notice: {}", text);
+                world.resource_mut::<Events<ServerNotice>>().send(ServerNotice(text));
+            }
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        ChannelType::OrderedReliable
+    }
+
+    fn plugin(app: &mut App) {
+        app.add_event::<ServerNotice>();
+        app.add_event::<AdminOutput>();
+    }
+}
@@ -0,0 +1,416 @@
+use crate::networking::admin_client::AdminMsgClient;
+use crate::networking::persistence::save_world;
+use bevy_app::App;
+use bevy_ecs::prelude::{Res, ResMut, Resource, World};
+use bevy_ecs::system::SystemState;
+use bevy_hierarchy::DespawnRecursiveExt;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use leknet::{
+    assign_role, AdmittedClients, BanList, ClientLinkStats, ClientRoles, EntityMap, LekServer,
+    NetId, PeerAddresses, Role, ServerMessage, TypeName,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+
+/// `token` lets clients run admin commands, remote administration is off while it's `None`
+#[derive(Resource, Clone, Debug)]
+pub struct AdminSettings {
+    pub token: Option<String>,
+    /// where `BanList` is loaded from on startup and saved to on every change
+    pub ban_list_path: Option<PathBuf>,
+}
+
+impl Default for AdminSettings {
+    fn default() -> Self {
+        Self {
+            token: None,
+            ban_list_path: Some(PathBuf::from("bans.txt")),
+        }
+    }
+}
+
+/// identities whose voice isn't relayed, so reconnecting doesn't lift a mute
+#[derive(Resource, Clone, Debug, Default)]
+pub struct MutedClients(pub HashSet<String>);
+
+/// whether `client_id` connected with a muted identity
+pub fn is_muted(world: &World, client_id: ClientId) -> bool {
+    world
+        .resource::<AdmittedClients>()
+        .0
+        .get(&client_id)
+        .map_or(false, |identity| world.resource::<MutedClients>().0.contains(identity))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdminCommand {
+    List,
+    Kick(ClientId),
+    /// a connected client's id, an address, or an identity
+    Ban(String),
+    /// an address or an identity
+    Unban(String),
+    /// a connected client's id, or an identity
+    Mute(String),
+    Unmute(String),
+    SetRole(ClientId, Role),
+    Despawn(NetId),
+    Notice(String),
+    Save,
+    Help,
+}
+
+const HELP: &str = "list | kick <client> | ban <client|address|identity> | unban <address|identity> | mute <client|identity> | unmute <client|identity> | role <client> <role> | despawn <net id> | notice <text> | save";
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<AdminCommand, String> {
+        let line = line.trim();
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            None => (line, ""),
+            Some((name, argument)) => (name, argument.trim()),
+        };
+        let client_id = || {
+            argument
+                .parse::<ClientId>()
+                .map_err(|_| format!("{} expects a client id, got {:?}", name, argument))
+        };
+        let text = || match argument.is_empty() {
+            true => Err(format!("{} expects an argument", name)),
+            false => Ok(argument.to_string()),
+        };
+        match name {
+            "list" => Ok(AdminCommand::List),
+            "kick" => Ok(AdminCommand::Kick(client_id()?)),
+            "ban" => Ok(AdminCommand::Ban(text()?)),
+            "unban" => Ok(AdminCommand::Unban(text()?)),
+            "mute" => Ok(AdminCommand::Mute(text()?)),
+            "unmute" => Ok(AdminCommand::Unmute(text()?)),
+            "role" => {
+                let (client, role) = argument
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(|| "role expects a client id and a role".to_string())?;
+                let client_id = client
+                    .parse::<ClientId>()
+                    .map_err(|_| format!("role expects a client id, got {:?}", client))?;
+                Ok(AdminCommand::SetRole(client_id, role.trim().parse()?))
+            }
+            "despawn" => argument
+                .parse::<u64>()
+                .map(|net_id| AdminCommand::Despawn(NetId(net_id)))
+                .map_err(|_| format!("despawn expects a net id, got {:?}", argument)),
+            "notice" => Ok(AdminCommand::Notice(text()?)),
+            "save" => Ok(AdminCommand::Save),
+            "help" => Ok(AdminCommand::Help),
+            _ => Err(format!("unknown command {:?}, try help", name)),
+        }
+    }
+}
+
+/// runs `command` and returns what it did
+pub fn run_admin_command(world: &mut World, command: AdminCommand) -> String {
+    match command {
+        AdminCommand::List => {
+            let admitted = world.resource::<AdmittedClients>();
+            let link_stats = world.resource::<ClientLinkStats>();
+            let muted = world.resource::<MutedClients>();
+            let roles = world.resource::<ClientRoles>();
+            let mut clients: Vec<_> = admitted.0.iter().collect();
+            clients.sort_by_key(|(client_id, _)| **client_id);
+            let mut lines = vec![format!("{} clients", clients.len())];
+            for (client_id, identity) in clients {
+                let rtt = link_stats
+                    .0
+                    .get(client_id)
+                    .map_or("?".to_string(), |stats| format!("{}ms", stats.rtt.as_millis()));
+                let muted = match muted.0.contains(identity) {
+                    true => " (muted)",
+                    false => "",
+                };
+                let role = roles.get(*client_id);
+                lines.push(format!("{} {} {} {}{}", client_id, identity, role, rtt, muted));
+            }
+            lines.join("\n")
+        }
+        AdminCommand::Kick(client_id) => match disconnect(world, client_id) {
+            true => format!("kicked client {}", client_id),
+            false => format!("no client {}", client_id),
+        },
+        AdminCommand::Ban(target) => {
+            let (identity, address) = match target.parse::<IpAddr>() {
+                Ok(address) => (None, Some(address)),
+                Err(_) => {
+                    // a connected client is banned by the address it connects from too, its name is easy to change
+                    let address = target
+                        .parse::<ClientId>()
+                        .ok()
+                        .and_then(|client_id| world.resource::<PeerAddresses>().0.get(&client_id).copied());
+                    (Some(identity_of(world, target)), address)
+                }
+            };
+            let mut ban_list = world.resource_mut::<BanList>();
+            ban_list.identities.extend(identity.clone());
+            ban_list.addresses.extend(address);
+            let ban_list = world.resource::<BanList>();
+            let peer_addresses = world.resource::<PeerAddresses>();
+            let banned: Vec<ClientId> = world
+                .resource::<AdmittedClients>()
+                .0
+                .iter()
+                .filter(|(client_id, identity)| {
+                    ban_list.is_banned(identity, peer_addresses.0.get(*client_id).copied())
+                })
+                .map(|(client_id, _)| *client_id)
+                .collect();
+            for client_id in banned {
+                disconnect(world, client_id);
+            }
+            save_ban_list(world);
+            let banned: Vec<String> = identity
+                .into_iter()
+                .chain(address.map(|address| address.to_string()))
+                .collect();
+            format!("banned {}", banned.join(" and "))
+        }
+        AdminCommand::Unban(target) => {
+            let mut ban_list = world.resource_mut::<BanList>();
+            let unbanned = match target.parse::<IpAddr>() {
+                Ok(address) => ban_list.addresses.remove(&address),
+                Err(_) => ban_list.identities.remove(&target),
+            };
+            match unbanned {
+                true => {
+                    save_ban_list(world);
+                    format!("unbanned {}", target)
+                }
+                false => format!("{} isn't banned", target),
+            }
+        }
+        AdminCommand::Mute(target) => {
+            let identity = identity_of(world, target);
+            world.resource_mut::<MutedClients>().0.insert(identity.clone());
+            format!("muted {}", identity)
+        }
+        AdminCommand::Unmute(target) => {
+            let identity = identity_of(world, target);
+            match world.resource_mut::<MutedClients>().0.remove(&identity) {
+                true => format!("unmuted {}", identity),
+                false => format!("{} isn't muted", identity),
+            }
+        }
+        AdminCommand::SetRole(client_id, role) => {
+            if !world.resource::<AdmittedClients>().contains(client_id) {
+                return format!("no client {}", client_id);
+            }
+            assign_role(world, client_id, role);
+            format!("client {} is now a {}", client_id, role)
+        }
+        AdminCommand::Despawn(net_id) => {
+            let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+                None => return format!("nothing has {:?}", net_id),
+                Some(entity) => *entity,
+            };
+            // removing `Networked` tells the clients
+            match world.get_entity_mut(entity) {
+                None => format!("nothing has {:?}", net_id),
+                Some(world_entity) => {
+                    world_entity.despawn_recursive();
+                    format!("despawned {:?}", net_id)
+                }
+            }
+        }
+        AdminCommand::Notice(text) => {
+            let mut system_state: SystemState<(ResMut<Server>, Res<AdmittedClients>)> = SystemState::new(world);
+            let (mut server, admitted) = system_state.get_mut(world);
+            let endpoint = server.endpoint_mut();
+            for client_id in admitted.of(endpoint) {
+                endpoint
+                    .send_lek_msg(client_id, AdminMsgClient::Notice(text.clone()))
+                    .unwrap();
+            }
+            format!("sent notice {:?}", text)
+        }
+        AdminCommand::Save => match save_world(world) {
+            Ok(()) => "saved the world".to_string(),
+            Err(e) => format!("couldn't save the world: {}", e),
+        },
+        AdminCommand::Help => HELP.to_string(),
+    }
+}
+
+/// the identity of the connected client `target` names, or `target` itself if it's no client id
+fn identity_of(world: &World, target: String) -> String {
+    let admitted = world.resource::<AdmittedClients>();
+    match target.parse::<ClientId>().ok().and_then(|client_id| admitted.0.get(&client_id)) {
+        None => target,
+        Some(identity) => identity.clone(),
+    }
+}
+
+fn disconnect(world: &mut World, client_id: ClientId) -> bool {
+    let mut server = world.resource_mut::<Server>();
+    let endpoint = server.endpoint_mut();
+    if !endpoint.clients().contains(&client_id) {
+        return false;
+    }
+    endpoint.disconnect_client(client_id).unwrap();
+    true
+}
+
+fn save_ban_list(world: &World) {
+    let path = match &world.resource::<AdminSettings>().ban_list_path {
+        None => return,
+        Some(path) => path.clone(),
+    };
+    let ban_list = world.resource::<BanList>();
+    let mut addresses: Vec<&IpAddr> = ban_list.addresses.iter().collect();
+    addresses.sort();
+    let mut identities: Vec<&String> = ban_list.identities.iter().collect();
+    identities.sort();
+    let contents: String = addresses
+        .into_iter()
+        .map(|address| format!("{}\n", address))
+        .chain(identities.into_iter().map(|identity| format!("{}\n", identity)))
+        .collect();
+    if let Err(e) = fs::write(&path, contents) {
+        println!("couldn't save the ban list to {:?}: {}", path, e);
+    }
+}
+
+pub(crate) fn load_ban_list(settings: Res<AdminSettings>, mut ban_list: ResMut<BanList>) {
+    let path = match &settings.ban_list_path {
+        None => return,
+        Some(path) => path,
+    };
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return,
+    };
+    // anything that reads as an address is one, everything else is an identity
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        match line.parse::<IpAddr>() {
+            Ok(address) => {
+                ban_list.addresses.insert(address);
+            }
+            Err(_) => {
+                ban_list.identities.insert(line.to_string());
+            }
+        }
+    }
+}
+
+/// compares without returning early, so the time taken doesn't tell how much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+/// lines typed into the terminal of a headless server
+#[derive(Resource)]
+pub(crate) struct AdminConsole(Mutex<Receiver<String>>);
+
+impl AdminConsole {
+    pub(crate) fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        });
+        Self(Mutex::new(receiver))
+    }
+}
+
+pub(crate) fn read_admin_console(world: &mut World) {
+    let lines: Vec<String> = match world.get_resource::<AdminConsole>() {
+        None => return,
+        Some(console) => console.0.lock().unwrap().try_iter().collect(),
+    };
+    for line in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match AdminCommand::parse(&line) {
+            Ok(command) => println!("{}", run_admin_command(world, command)),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AdminMsgServer {
+    /// the server's `AdminSettings::token` and the command to run
+    Command(String, AdminCommand),
+}
+
+impl TypeName for AdminMsgServer {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::AdminMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for AdminMsgServer {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        match self {
+            AdminMsgServer::Command(token, command) => {
+                let authorized = world
+                    .resource::<AdminSettings>()
+                    .token
+                    .as_ref()
+                    .map_or(false, |token2| constant_time_eq(token2.as_bytes(), token.as_bytes()));
+                let output = match authorized {
+                    true => {
+                        println!("client {} ran {:?}", client_id, command);
+                        run_admin_command(world, command)
+                    }
+                    false => {
+                        println!("client {} tried to run {:?} without permission", client_id, command);
+                        "not authorized".to_string()
+                    }
+                };
+                let mut server = world.resource_mut::<Server>();
+                let endpoint = server.endpoint_mut();
+                // a kick or ban may have just disconnected the sender
+                if endpoint.clients().contains(&client_id) {
+                    endpoint
+                        .send_lek_msg(client_id, AdminMsgClient::Output(output))
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        ChannelType::OrderedReliable
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<AdminSettings>();
+        app.init_resource::<MutedClients>();
+        app.add_startup_system(load_ban_list);
+        app.add_system(read_admin_console);
+    }
+}
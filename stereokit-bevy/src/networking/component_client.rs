@@ -0,0 +1,66 @@
+use crate::networking::replication::ReplicationRegistry;
+use bevy_app::App;
+use bevy_ecs::prelude::World;
+use bevy_quinnet::shared::channel::ChannelType;
+use leknet::{ClientMessage, EntityMap, NetId, TypeName};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ComponentMsgClient {
+    Changed(NetId, String, Vec<u8>),
+    Removed(NetId, String),
+}
+
+impl TypeName for ComponentMsgClient {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::ComponentMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for ComponentMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            ComponentMsgClient::Changed(net_id, name, bytes) => {
+                component_changed_msg(world, net_id, name, bytes)
+            }
+            ComponentMsgClient::Removed(net_id, name) => component_removed_msg(world, net_id, name),
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            ComponentMsgClient::Changed(_, _, _) => ChannelType::OrderedReliable,
+            ComponentMsgClient::Removed(_, _) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<ReplicationRegistry>();
+    }
+}
+
+fn component_changed_msg(world: &mut World, net_id: NetId, name: String, bytes: Vec<u8>) {
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    if let Some(replicated) = world.resource::<ReplicationRegistry>().get(&name) {
+        (replicated.apply)(world, entity, &bytes);
+    }
+}
+
+fn component_removed_msg(world: &mut World, net_id: NetId, name: String) {
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    if let Some(replicated) = world.resource::<ReplicationRegistry>().get(&name) {
+        (replicated.remove)(world, entity);
+    }
+}
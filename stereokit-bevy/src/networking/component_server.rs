@@ -0,0 +1,130 @@
+use crate::networking::component_client::ComponentMsgClient;
+use crate::networking::interest::{is_relevant, ClientInterest};
+use crate::networking::ownership_server::has_authority;
+use crate::networking::validation::{send_correction, validate, Validation};
+use crate::networking::replication::ReplicationRegistry;
+use bevy_app::App;
+use bevy_ecs::prelude::{Res, ResMut, World};
+use bevy_ecs::system::SystemState;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use leknet::{AdmittedClients, EntityMap, LekServer, NetId, ServerMessage, TypeName};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ComponentMsgServer {
+    Changed(NetId, String, Vec<u8>),
+    Removed(NetId, String),
+}
+
+impl TypeName for ComponentMsgServer {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::ComponentMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for ComponentMsgServer {
+    fn server(mut self, world: &mut World, client_id: ClientId) {
+        let validation = validate(world, client_id, &mut self);
+        let net_id = match &self {
+            ComponentMsgServer::Changed(net_id, _, _) => *net_id,
+            ComponentMsgServer::Removed(net_id, _) => *net_id,
+        };
+        if validation == Validation::Reject {
+            send_correction(world, client_id, net_id);
+            return;
+        }
+        match self {
+            ComponentMsgServer::Changed(net_id, name, bytes) => {
+                component_changed_msg(world, client_id, net_id, name, bytes)
+            }
+            ComponentMsgServer::Removed(net_id, name) => {
+                component_removed_msg(world, client_id, net_id, name)
+            }
+        }
+        if validation == Validation::Clamped {
+            send_correction(world, client_id, net_id);
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            ComponentMsgServer::Changed(_, _, _) => ChannelType::OrderedReliable,
+            ComponentMsgServer::Removed(_, _) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<ReplicationRegistry>();
+    }
+}
+
+fn component_changed_msg(
+    world: &mut World,
+    client_id: ClientId,
+    net_id: NetId,
+    name: String,
+    bytes: Vec<u8>,
+) {
+    if !has_authority(world, client_id, net_id) {
+        return;
+    }
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    let replicated = match world.resource::<ReplicationRegistry>().get(&name) {
+        None => return,
+        Some(replicated) => replicated,
+    };
+    (replicated.apply)(world, entity, &bytes);
+    let mut system_state: SystemState<(ResMut<Server>, Option<Res<ClientInterest>>, Res<AdmittedClients>)> =
+        SystemState::new(world);
+    let (mut server, interest, admitted) = system_state.get_mut(world);
+    let endpoint = server.endpoint_mut();
+    for client_id2 in admitted.of(endpoint) {
+        if client_id2 == client_id || !is_relevant(interest.as_deref(), client_id2, net_id) {
+            continue;
+        }
+        endpoint
+            .send_lek_msg(
+                client_id2,
+                ComponentMsgClient::Changed(net_id, name.clone(), bytes.clone()),
+            )
+            .unwrap();
+    }
+}
+
+fn component_removed_msg(world: &mut World, client_id: ClientId, net_id: NetId, name: String) {
+    if !has_authority(world, client_id, net_id) {
+        return;
+    }
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    let replicated = match world.resource::<ReplicationRegistry>().get(&name) {
+        None => return,
+        Some(replicated) => replicated,
+    };
+    (replicated.remove)(world, entity);
+    let mut system_state: SystemState<(ResMut<Server>, Option<Res<ClientInterest>>, Res<AdmittedClients>)> =
+        SystemState::new(world);
+    let (mut server, interest, admitted) = system_state.get_mut(world);
+    let endpoint = server.endpoint_mut();
+    for client_id2 in admitted.of(endpoint) {
+        if client_id2 == client_id || !is_relevant(interest.as_deref(), client_id2, net_id) {
+            continue;
+        }
+        endpoint
+            .send_lek_msg(client_id2, ComponentMsgClient::Removed(net_id, name.clone()))
+            .unwrap();
+    }
+}
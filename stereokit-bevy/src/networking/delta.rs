@@ -0,0 +1,316 @@
+use crate::networking::prediction::is_newer;
+use crate::networking::quantize::{CompactTransform, QuantizationSettings, TransformEncoding};
+use crate::networking::ModelData2;
+use bevy_ecs::prelude::{EventReader, ResMut, Resource};
+use bevy_quinnet::server::ConnectionLostEvent;
+use bevy_quinnet::shared::ClientId;
+use glam::{Quat, Vec3};
+use leknet::NetId;
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use stereokit::{Color128, RenderLayer};
+
+const TRANSLATION: u8 = 1 << 0;
+const ROTATION: u8 = 1 << 1;
+const SCALE: u8 = 1 << 2;
+const COLOR: u8 = 1 << 3;
+const RENDER_LAYER: u8 = 1 << 4;
+const COMPACT: u8 = 1 << 5;
+
+/// the fields of a `ModelData2` that changed since a baseline, encoded as a mask followed by only those fields
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelDelta {
+    translation: Option<Vec3>,
+    rotation: Option<Quat>,
+    scale: Option<Vec3>,
+    /// the whole transform quantized, sent instead of the three fields above with `TransformEncoding::Compact`
+    compact: Option<CompactTransform>,
+    color128: Option<Color128>,
+    render_layer: Option<RenderLayer>,
+}
+
+impl ModelDelta {
+    pub fn between(baseline: Option<&ModelData2>, state: &ModelData2) -> Self {
+        let changed = |unchanged: bool| baseline.is_none() || !unchanged;
+        let transform = &state.transform;
+        Self {
+            translation: changed(baseline.map_or(false, |b| b.transform.translation == transform.translation))
+                .then_some(transform.translation),
+            rotation: changed(baseline.map_or(false, |b| b.transform.rotation == transform.rotation))
+                .then_some(transform.rotation),
+            scale: changed(baseline.map_or(false, |b| b.transform.scale == transform.scale))
+                .then_some(transform.scale),
+            compact: None,
+            color128: changed(baseline.map_or(false, |b| b.color128 == state.color128))
+                .then_some(state.color128),
+            render_layer: changed(baseline.map_or(false, |b| b.render_layer == state.render_layer))
+                .then_some(state.render_layer),
+        }
+    }
+
+    /// like `between`, but a changed transform is sent whole and quantized when `encoding` is compact
+    pub fn between_encoded(
+        baseline: Option<&ModelData2>,
+        state: &ModelData2,
+        encoding: TransformEncoding,
+        settings: &QuantizationSettings,
+    ) -> Self {
+        let mut delta = Self::between(baseline, state);
+        let transform_changed = delta.translation.is_some() || delta.rotation.is_some() || delta.scale.is_some();
+        if encoding == TransformEncoding::Compact && transform_changed {
+            delta.translation = None;
+            delta.rotation = None;
+            delta.scale = None;
+            delta.compact = Some(CompactTransform::encode(&state.transform, settings));
+        }
+        delta
+    }
+
+    fn mask(&self) -> u8 {
+        let mut mask = 0;
+        if self.translation.is_some() {
+            mask |= TRANSLATION;
+        }
+        if self.rotation.is_some() {
+            mask |= ROTATION;
+        }
+        if self.scale.is_some() {
+            mask |= SCALE;
+        }
+        if self.compact.is_some() {
+            mask |= COMPACT;
+        }
+        if self.color128.is_some() {
+            mask |= COLOR;
+        }
+        if self.render_layer.is_some() {
+            mask |= RENDER_LAYER;
+        }
+        mask
+    }
+
+    pub fn is_full(&self) -> bool {
+        let transform = self.compact.is_some()
+            || (self.translation.is_some() && self.rotation.is_some() && self.scale.is_some());
+        transform && self.color128.is_some() && self.render_layer.is_some()
+    }
+
+    /// `baseline` with this delta's fields written over it, `None` if a field is missing and there's no baseline,
+    /// `settings` has to match the sender's to decode a compact transform
+    pub fn apply(&self, baseline: Option<&ModelData2>, settings: &QuantizationSettings) -> Option<ModelData2> {
+        let mut state = match baseline {
+            Some(baseline) => baseline.clone(),
+            None if self.is_full() => ModelData2 {
+                transform: Default::default(),
+                color128: self.color128?,
+                render_layer: self.render_layer?,
+            },
+            None => return None,
+        };
+        if let Some(translation) = self.translation {
+            state.transform.translation = translation;
+        }
+        if let Some(rotation) = self.rotation {
+            state.transform.rotation = rotation;
+        }
+        if let Some(scale) = self.scale {
+            state.transform.scale = scale;
+        }
+        if let Some(compact) = &self.compact {
+            state.transform = compact.decode(settings);
+        }
+        if let Some(color128) = self.color128 {
+            state.color128 = color128;
+        }
+        if let Some(render_layer) = self.render_layer {
+            state.render_layer = render_layer;
+        }
+        Some(state)
+    }
+}
+
+impl Serialize for ModelDelta {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // bincode doesn't write tuple lengths, so absent fields take no space at all
+        let mut tuple = serializer.serialize_tuple(7)?;
+        tuple.serialize_element(&self.mask())?;
+        if let Some(translation) = &self.translation {
+            tuple.serialize_element(translation)?;
+        }
+        if let Some(rotation) = &self.rotation {
+            tuple.serialize_element(rotation)?;
+        }
+        if let Some(scale) = &self.scale {
+            tuple.serialize_element(scale)?;
+        }
+        if let Some(compact) = &self.compact {
+            tuple.serialize_element(compact)?;
+        }
+        if let Some(color128) = &self.color128 {
+            tuple.serialize_element(color128)?;
+        }
+        if let Some(render_layer) = &self.render_layer {
+            tuple.serialize_element(render_layer)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ModelDelta {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DeltaVisitor;
+
+        impl<'de> Visitor<'de> for DeltaVisitor {
+            type Value = ModelDelta;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a change mask followed by the changed fields")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ModelDelta, A::Error> {
+                let missing = || A::Error::custom("model delta is missing a field its mask promised");
+                let mask: u8 = seq.next_element()?.ok_or_else(missing)?;
+                let mut delta = ModelDelta::default();
+                if mask & TRANSLATION != 0 {
+                    delta.translation = Some(seq.next_element()?.ok_or_else(missing)?);
+                }
+                if mask & ROTATION != 0 {
+                    delta.rotation = Some(seq.next_element()?.ok_or_else(missing)?);
+                }
+                if mask & SCALE != 0 {
+                    delta.scale = Some(seq.next_element()?.ok_or_else(missing)?);
+                }
+                if mask & COMPACT != 0 {
+                    delta.compact = Some(seq.next_element()?.ok_or_else(missing)?);
+                }
+                if mask & COLOR != 0 {
+                    delta.color128 = Some(seq.next_element()?.ok_or_else(missing)?);
+                }
+                if mask & RENDER_LAYER != 0 {
+                    delta.render_layer = Some(seq.next_element()?.ok_or_else(missing)?);
+                }
+                Ok(delta)
+            }
+        }
+
+        deserializer.deserialize_tuple(7, DeltaVisitor)
+    }
+}
+
+const MAX_STATES: usize = 32;
+
+#[derive(Default)]
+struct Baseline {
+    next_seq: u32,
+    sent: VecDeque<(u32, ModelData2)>,
+    acked: Option<(u32, ModelData2)>,
+}
+
+/// per client and entity, the states the server sent and the latest one the client confirmed
+#[derive(Resource, Default)]
+pub(crate) struct DeltaBaselines(HashMap<(ClientId, NetId), Baseline>);
+
+impl DeltaBaselines {
+    /// the sequence number, baseline it's relative to, and delta to send `state` to `client_id` with
+    pub(crate) fn encode(
+        &mut self,
+        client_id: ClientId,
+        net_id: NetId,
+        state: &ModelData2,
+        encoding: TransformEncoding,
+        settings: &QuantizationSettings,
+    ) -> (u32, Option<u32>, ModelDelta) {
+        let baseline = self.0.entry((client_id, net_id)).or_default();
+        let seq = baseline.next_seq;
+        baseline.next_seq = baseline.next_seq.wrapping_add(1);
+        // compared as the client will decode it, so movement below the quantization step isn't resent
+        let state = match encoding {
+            TransformEncoding::Raw => state.clone(),
+            TransformEncoding::Compact => ModelData2 {
+                transform: CompactTransform::encode(&state.transform, settings).decode(settings),
+                ..state.clone()
+            },
+        };
+        let acked = baseline.acked.as_ref();
+        let delta = ModelDelta::between_encoded(acked.map(|(_, acked)| acked), &state, encoding, settings);
+        let sent = delta.apply(acked.map(|(_, acked)| acked), settings).unwrap_or(state);
+        let acked_seq = acked.map(|(acked_seq, _)| *acked_seq);
+        baseline.sent.push_back((seq, sent));
+        while baseline.sent.len() > MAX_STATES {
+            baseline.sent.pop_front();
+        }
+        (seq, acked_seq, delta)
+    }
+
+    pub(crate) fn ack(&mut self, client_id: ClientId, net_id: NetId, seq: u32) {
+        let baseline = match self.0.get_mut(&(client_id, net_id)) {
+            None => return,
+            Some(baseline) => baseline,
+        };
+        if baseline.acked.as_ref().map_or(false, |(acked_seq, _)| !is_newer(seq, *acked_seq)) {
+            return;
+        }
+        if let Some(index) = baseline.sent.iter().position(|(sent_seq, _)| *sent_seq == seq) {
+            baseline.acked = Some(baseline.sent[index].clone());
+            baseline.sent.drain(..index);
+        }
+    }
+
+    /// the next update to `client_id` is sent in full, e.g. because it lost track of the entity
+    pub(crate) fn reset(&mut self, client_id: ClientId, net_id: NetId) {
+        if let Some(baseline) = self.0.get_mut(&(client_id, net_id)) {
+            baseline.acked = None;
+            baseline.sent.clear();
+        }
+    }
+
+    /// forgets everything sent about `net_id`, once it's despawned
+    pub(crate) fn forget(&mut self, net_id: NetId) {
+        self.0.retain(|(_, net_id2), _| *net_id2 != net_id);
+    }
+}
+
+pub(crate) fn forget_client_baselines(
+    mut disconnected: EventReader<ConnectionLostEvent>,
+    mut baselines: ResMut<DeltaBaselines>,
+) {
+    for client in disconnected.iter() {
+        baselines.0.retain(|(client_id, _), _| *client_id != client.id);
+    }
+}
+
+/// states received from the server per entity, the baselines later deltas refer to
+#[derive(Resource, Default)]
+pub(crate) struct ReceivedStates(HashMap<NetId, VecDeque<(u32, ModelData2)>>);
+
+impl ReceivedStates {
+    pub(crate) fn decode(
+        &mut self,
+        net_id: NetId,
+        seq: u32,
+        baseline: Option<u32>,
+        delta: &ModelDelta,
+        settings: &QuantizationSettings,
+    ) -> Option<ModelData2> {
+        let states = self.0.entry(net_id).or_default();
+        let state = match baseline {
+            None => delta.apply(None, settings)?,
+            Some(baseline) => {
+                let (_, baseline) = states.iter().find(|(received_seq, _)| *received_seq == baseline)?;
+                delta.apply(Some(baseline), settings)?
+            }
+        };
+        states.push_back((seq, state.clone()));
+        while states.len() > MAX_STATES {
+            states.pop_front();
+        }
+        Some(state)
+    }
+
+    pub(crate) fn forget(&mut self, net_id: NetId) {
+        self.0.remove(&net_id);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::networking::delta::ReceivedStates;
+use crate::networking::entity_server::EntityMsgServer;
+use bevy_app::App;
+use bevy_ecs::prelude::{RemovedComponents, ResMut, World};
+use bevy_hierarchy::{Children, DespawnRecursiveExt};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::shared::channel::ChannelType;
+use leknet::{ClientMessage, EntityMap, LekClient, NetId, Networked, TypeName};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum EntityMsgClient {
+    Despawned(NetId),
+}
+
+impl TypeName for EntityMsgClient {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::EntityMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for EntityMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            EntityMsgClient::Despawned(net_id) => despawned_msg(world, net_id),
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            EntityMsgClient::Despawned(_) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.add_system(networked_removed);
+    }
+}
+
+fn despawned_msg(world: &mut World, net_id: NetId) {
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    // children go with it, the server forgets them the same way
+    let mut despawned = vec![entity];
+    let mut index = 0;
+    while index < despawned.len() {
+        if let Some(children) = world.get::<Children>(despawned[index]) {
+            despawned.extend(children.iter().copied());
+        }
+        index += 1;
+    }
+    // unmap first so `networked_removed` doesn't send the despawns back to the server
+    for entity in despawned {
+        let unmapped = world.resource_mut::<EntityMap>().remove_by_left(&entity);
+        if let Some((_, net_id)) = unmapped {
+            world.resource_mut::<ReceivedStates>().forget(net_id);
+        }
+    }
+    if let Some(world_entity) = world.get_entity_mut(entity) {
+        world_entity.despawn_recursive();
+    }
+}
+
+fn networked_removed(
+    mut removed: RemovedComponents<Networked>,
+    mut entity_map: ResMut<EntityMap>,
+    mut client: ResMut<Client>,
+) {
+    for entity in removed.iter() {
+        if let Some((_, net_id)) = entity_map.remove_by_left(&entity) {
+            if let Some(connection) = client.get_connection_mut() {
+                connection
+                    .send_lek_msg(EntityMsgServer::Despawned(net_id))
+                    .unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::networking::delta::DeltaBaselines;
+use crate::networking::entity_client::EntityMsgClient;
+use crate::networking::interest::{is_relevant, ClientInterest};
+use crate::networking::ownership_server::has_authority;
+use crate::networking::permissions::{is_permitted, Permission};
+use bevy_app::App;
+use bevy_ecs::prelude::{RemovedComponents, Res, ResMut, World};
+use bevy_ecs::system::SystemState;
+use bevy_hierarchy::DespawnRecursiveExt;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use leknet::{AdmittedClients, EntityMap, LekServer, NetId, Networked, ServerMessage, TypeName};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum EntityMsgServer {
+    Despawned(NetId),
+}
+
+impl TypeName for EntityMsgServer {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::EntityMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for EntityMsgServer {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        match self {
+            EntityMsgServer::Despawned(net_id) => despawned_msg(world, client_id, net_id),
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            EntityMsgServer::Despawned(_) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.add_system(networked_removed);
+    }
+}
+
+fn despawned_msg(world: &mut World, client_id: ClientId, net_id: NetId) {
+    // moderators may despawn what other clients own
+    if !has_authority(world, client_id, net_id)
+        && !is_permitted(world, client_id, Permission::DespawnAny)
+    {
+        return;
+    }
+    let entity = match world.resource_mut::<EntityMap>().remove_by_right(&net_id) {
+        None => return,
+        Some((entity, _)) => entity,
+    };
+    if let Some(world_entity) = world.get_entity_mut(entity) {
+        world_entity.despawn_recursive();
+    }
+    let mut system_state: SystemState<(
+        ResMut<Server>,
+        Option<ResMut<ClientInterest>>,
+        Option<ResMut<DeltaBaselines>>,
+        Res<AdmittedClients>,
+    )> = SystemState::new(world);
+    let (mut server, mut interest, baselines, admitted) = system_state.get_mut(world);
+    if let Some(mut baselines) = baselines {
+        baselines.forget(net_id);
+    }
+    let endpoint = server.endpoint_mut();
+    for client_id2 in admitted.of(endpoint) {
+        if client_id2 == client_id || !is_relevant(interest.as_deref(), client_id2, net_id) {
+            continue;
+        }
+        endpoint
+            .send_lek_msg(client_id2, EntityMsgClient::Despawned(net_id))
+            .unwrap();
+    }
+    if let Some(interest) = interest.as_mut() {
+        interest.forget(net_id);
+    }
+}
+
+/// despawns done by the server itself, e.g. when a player's client disconnects
+fn networked_removed(
+    mut removed: RemovedComponents<Networked>,
+    mut entity_map: ResMut<EntityMap>,
+    mut server: ResMut<Server>,
+    mut interest: Option<ResMut<ClientInterest>>,
+    mut baselines: Option<ResMut<DeltaBaselines>>,
+    admitted: Res<AdmittedClients>,
+) {
+    for entity in removed.iter() {
+        if let Some((_, net_id)) = entity_map.remove_by_left(&entity) {
+            if let Some(baselines) = baselines.as_mut() {
+                baselines.forget(net_id);
+            }
+            if let Some(endpoint) = server.get_endpoint_mut() {
+                for client_id in admitted.of(endpoint) {
+                    if !is_relevant(interest.as_deref(), client_id, net_id) {
+                        continue;
+                    }
+                    endpoint
+                        .send_lek_msg(client_id, EntityMsgClient::Despawned(net_id))
+                        .unwrap();
+                }
+            }
+            if let Some(interest) = interest.as_mut() {
+                interest.forget(net_id);
+            }
+        }
+    }
+}
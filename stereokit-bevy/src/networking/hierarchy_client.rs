@@ -0,0 +1,153 @@
+use crate::networking::hierarchy_server::HierarchyMsgServer;
+use crate::networking::ownership_client::Authority;
+use crate::networking::pending::PendingUpdateSettings;
+use bevy_app::App;
+use bevy_ecs::prelude::{
+    Changed, Commands, Entity, Query, RemovedComponents, Res, ResMut, Resource, With, World,
+};
+use bevy_hierarchy::{BuildChildren, Parent};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::shared::channel::ChannelType;
+use leknet::{ClientMessage, EntityMap, LekClient, NetId, Networked, TypeName};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum HierarchyMsgClient {
+    ParentChanged(NetId, Option<NetId>),
+}
+
+/// parent changes waiting for the child or the parent to be mapped, keyed by the child
+#[derive(Resource, Default)]
+pub(crate) struct PendingParents(HashMap<NetId, (Instant, Option<NetId>)>);
+
+impl PendingParents {
+    pub(crate) fn defer(&mut self, child: NetId, parent: Option<NetId>) {
+        self.0.insert(child, (Instant::now(), parent));
+    }
+}
+
+/// entities whose `Parent` was last written by the network, so the change isn't echoed back
+#[derive(Resource, Default)]
+pub(crate) struct ReceivedParents(pub(crate) HashSet<Entity>);
+
+impl TypeName for HierarchyMsgClient {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::HierarchyMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for HierarchyMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            HierarchyMsgClient::ParentChanged(child, parent) => {
+                world.resource_mut::<PendingParents>().defer(child, parent);
+            }
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            HierarchyMsgClient::ParentChanged(_, _) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<PendingParents>();
+        app.init_resource::<ReceivedParents>();
+        app.add_system(apply_pending_parents);
+        app.add_system(parent_changed);
+        app.add_system(parent_removed);
+    }
+}
+
+/// applies parent changes once both sides are mapped, and drops them after `PendingUpdateSettings::timeout`
+pub(crate) fn apply_pending_parents(
+    mut pending: ResMut<PendingParents>,
+    mut received: ResMut<ReceivedParents>,
+    entity_map: Res<EntityMap>,
+    settings: Option<Res<PendingUpdateSettings>>,
+    has_parent: Query<(), With<Parent>>,
+    mut commands: Commands,
+) {
+    let timeout = settings.map_or(PendingUpdateSettings::default().timeout, |settings| settings.timeout);
+    let expired = |child: &NetId, since: &Instant| {
+        let expired = since.elapsed() >= timeout;
+        if expired {
+            println!("dropping parent change for {:?}, it was never mapped", child);
+        }
+        expired
+    };
+    pending.0.retain(|net_id, (since, parent)| {
+        let child = match entity_map.get_by_right(net_id) {
+            None => return !expired(net_id, since),
+            Some(child) => *child,
+        };
+        match parent {
+            // removing a parent that isn't there changes nothing, so nothing would clear the entry
+            None if !has_parent.contains(child) => return false,
+            None => {
+                commands.entity(child).remove_parent();
+            }
+            Some(parent) => match entity_map.get_by_right(parent) {
+                None => return !expired(net_id, since),
+                Some(parent) => {
+                    commands.entity(child).set_parent(*parent);
+                }
+            },
+        }
+        received.0.insert(child);
+        false
+    });
+}
+
+fn parent_changed(
+    query: Query<(Entity, &Parent), (Changed<Parent>, With<Networked>)>,
+    authority: Query<(), With<Authority>>,
+    mut client: ResMut<Client>,
+    entity_map: Res<EntityMap>,
+    mut received: ResMut<ReceivedParents>,
+) {
+    for (entity, parent) in query.iter() {
+        // cleared whoever has authority, a stale entry would swallow the first change after gaining it
+        if received.0.remove(&entity) || !authority.contains(entity) {
+            continue;
+        }
+        // parenting to an entity that isn't networked stays local
+        if let (Some(connection), Some(child), Some(parent)) = (
+            client.get_connection_mut(),
+            entity_map.get_by_left(&entity),
+            entity_map.get_by_left(&parent.get()),
+        ) {
+            connection
+                .send_lek_msg(HierarchyMsgServer::ParentChanged(*child, Some(*parent)))
+                .unwrap()
+        }
+    }
+}
+
+fn parent_removed(
+    mut removed: RemovedComponents<Parent>,
+    networked: Query<(), (With<Networked>, With<Authority>)>,
+    mut client: ResMut<Client>,
+    entity_map: Res<EntityMap>,
+    mut received: ResMut<ReceivedParents>,
+) {
+    for entity in removed.iter() {
+        if received.0.remove(&entity) || !networked.contains(entity) {
+            continue;
+        }
+        if let (Some(connection), Some(child)) = (client.get_connection_mut(), entity_map.get_by_left(&entity)) {
+            connection
+                .send_lek_msg(HierarchyMsgServer::ParentChanged(*child, None))
+                .unwrap()
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::networking::hierarchy_client::{
+    apply_pending_parents, HierarchyMsgClient, PendingParents, ReceivedParents,
+};
+use crate::networking::interest::{is_relevant, ClientInterest};
+use crate::networking::ownership_server::has_authority;
+use crate::networking::validation::{send_correction, validate, Validation};
+use bevy_app::App;
+use bevy_ecs::prelude::{Changed, Entity, Query, RemovedComponents, Res, ResMut, With, World};
+use bevy_ecs::system::SystemState;
+use bevy_hierarchy::Parent;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use leknet::{AdmittedClients, EntityMap, LekServer, NetId, Networked, ServerMessage, TypeName};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum HierarchyMsgServer {
+    ParentChanged(NetId, Option<NetId>),
+}
+
+impl TypeName for HierarchyMsgServer {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::HierarchyMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for HierarchyMsgServer {
+    fn server(mut self, world: &mut World, client_id: ClientId) {
+        let validation = validate(world, client_id, &mut self);
+        let net_id = match &self {
+            HierarchyMsgServer::ParentChanged(net_id, _) => *net_id,
+        };
+        if validation == Validation::Reject {
+            send_correction(world, client_id, net_id);
+            return;
+        }
+        match self {
+            HierarchyMsgServer::ParentChanged(child, parent) => {
+                parent_changed_msg(world, client_id, child, parent)
+            }
+        }
+        if validation == Validation::Clamped {
+            send_correction(world, client_id, net_id);
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            HierarchyMsgServer::ParentChanged(_, _) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<PendingParents>();
+        app.init_resource::<ReceivedParents>();
+        app.add_system(apply_pending_parents);
+        app.add_system(parent_changed);
+        app.add_system(parent_removed);
+    }
+}
+
+fn parent_changed_msg(
+    world: &mut World,
+    client_id: ClientId,
+    child: NetId,
+    parent: Option<NetId>,
+) {
+    if !has_authority(world, client_id, child) {
+        return;
+    }
+    world.resource_mut::<PendingParents>().defer(child, parent);
+    let mut system_state: SystemState<(ResMut<Server>, Option<Res<ClientInterest>>, Res<AdmittedClients>)> =
+        SystemState::new(world);
+    let (mut server, interest, admitted) = system_state.get_mut(world);
+    let endpoint = server.endpoint_mut();
+    for client_id2 in admitted.of(endpoint) {
+        if client_id2 == client_id || !is_relevant(interest.as_deref(), client_id2, child) {
+            continue;
+        }
+        endpoint
+            .send_lek_msg(client_id2, HierarchyMsgClient::ParentChanged(child, parent))
+            .unwrap();
+    }
+}
+
+fn broadcast(
+    server: &mut Server,
+    interest: Option<&ClientInterest>,
+    admitted: &AdmittedClients,
+    child: NetId,
+    parent: Option<NetId>,
+) {
+    if let Some(endpoint) = server.get_endpoint_mut() {
+        for client_id in admitted.of(endpoint) {
+            if !is_relevant(interest, client_id, child) {
+                continue;
+            }
+            endpoint
+                .send_lek_msg(client_id, HierarchyMsgClient::ParentChanged(child, parent))
+                .unwrap();
+        }
+    }
+}
+
+fn parent_changed(
+    query: Query<(Entity, &Parent), (Changed<Parent>, With<Networked>)>,
+    mut server: ResMut<Server>,
+    entity_map: Res<EntityMap>,
+    mut received: ResMut<ReceivedParents>,
+    interest: Option<Res<ClientInterest>>,
+    admitted: Res<AdmittedClients>,
+) {
+    for (entity, parent) in query.iter() {
+        if received.0.remove(&entity) {
+            continue;
+        }
+        if let (Some(child), Some(parent)) = (
+            entity_map.get_by_left(&entity),
+            entity_map.get_by_left(&parent.get()),
+        ) {
+            broadcast(&mut server, interest.as_deref(), &admitted, *child, Some(*parent));
+        }
+    }
+}
+
+fn parent_removed(
+    mut removed: RemovedComponents<Parent>,
+    networked: Query<(), With<Networked>>,
+    mut server: ResMut<Server>,
+    entity_map: Res<EntityMap>,
+    mut received: ResMut<ReceivedParents>,
+    interest: Option<Res<ClientInterest>>,
+    admitted: Res<AdmittedClients>,
+) {
+    for entity in removed.iter() {
+        if received.0.remove(&entity) || !networked.contains(entity) {
+            continue;
+        }
+        if let Some(child) = entity_map.get_by_left(&entity) {
+            broadcast(&mut server, interest.as_deref(), &admitted, *child, None);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::networking::id_server::IdMsgServer;
+use crate::networking::NET_ID_RANGE_SIZE;
+use bevy_app::App;
+use bevy_ecs::prelude::{ResMut, Resource, World};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::shared::channel::ChannelType;
+use leknet::{ClientMessage, LekClient, NetId, TypeName};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// `NetId`s the server granted this client, so it can name what it spawns without asking first
+#[derive(Resource, Default)]
+pub struct NetIdPool {
+    ranges: VecDeque<Range<u64>>,
+    /// the server granted the first range of this connection, the pool may have run dry since
+    granted: bool,
+    requested: bool,
+}
+
+impl NetIdPool {
+    pub fn next(&mut self) -> Option<NetId> {
+        while let Some(range) = self.ranges.front_mut() {
+            match range.next() {
+                Some(id) => return Some(NetId(id)),
+                None => {
+                    self.ranges.pop_front();
+                }
+            }
+        }
+        None
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.ranges.iter().map(|range| range.end - range.start).sum()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum IdMsgClient {
+    GrantRange(u64, u64),
+}
+
+impl TypeName for IdMsgClient {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::IdMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for IdMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            IdMsgClient::GrantRange(start, end) => {
+                let mut pool = world.resource_mut::<NetIdPool>();
+                pool.ranges.push_back(start..end);
+                pool.granted = true;
+                pool.requested = false;
+            }
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            IdMsgClient::GrantRange(_, _) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<NetIdPool>();
+        app.add_system(request_more_ids);
+    }
+}
+
+/// asks for the next range well before this one runs out
+fn request_more_ids(mut pool: ResMut<NetIdPool>, mut client: ResMut<Client>) {
+    let connection = match client.get_connection_mut() {
+        Some(connection) if connection.is_connected() => connection,
+        _ => {
+            // ranges are granted per connection, the next one starts with a fresh grant
+            if pool.granted {
+                *pool = NetIdPool::default();
+            }
+            return;
+        }
+    };
+    // the first range is granted on admission
+    if !pool.granted || pool.requested || pool.remaining() > NET_ID_RANGE_SIZE / 2 {
+        return;
+    }
+    connection.send_lek_msg(IdMsgServer::RequestRange).unwrap();
+    pool.requested = true;
+}
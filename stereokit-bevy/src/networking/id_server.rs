@@ -0,0 +1,138 @@
+use crate::networking::id_client::IdMsgClient;
+use crate::networking::NET_ID_RANGE_SIZE;
+use bevy_app::App;
+use bevy_ecs::event::{Events, ManualEventReader};
+use bevy_ecs::prelude::{EventReader, Local, ResMut, Resource, World};
+use bevy_quinnet::server::{ConnectionLostEvent, Server};
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use leknet::{ClientAdmitted, EntityMap, LekServer, NetId, NetIdAllocator, ServerMessage, TypeName};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum IdMsgServer {
+    RequestRange,
+}
+
+impl TypeName for IdMsgServer {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::IdMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for IdMsgServer {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        match self {
+            IdMsgServer::RequestRange => grant_range(world, client_id),
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            IdMsgServer::RequestRange => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<GrantedIds>();
+        app.add_system(grant_initial_range);
+        app.add_system(forget_granted_ids);
+    }
+}
+
+/// how many granted ranges a client may hold without having spawned anything from them
+const MAX_UNUSED_RANGES: usize = 2;
+
+/// the `NetId` ranges handed to each client, a client may only spawn entities with ids from its own ranges
+#[derive(Resource, Default)]
+pub struct GrantedIds {
+    ranges: HashMap<ClientId, Vec<Range<u64>>>,
+    /// the highest id each client spawned something with, ranges are used in order
+    highest_claimed: HashMap<ClientId, u64>,
+}
+
+impl GrantedIds {
+    pub fn contains(&self, client_id: ClientId, net_id: NetId) -> bool {
+        self.ranges
+            .get(&client_id)
+            .map_or(false, |ranges| ranges.iter().any(|range| range.contains(&net_id.0)))
+    }
+
+    pub(crate) fn insert(&mut self, client_id: ClientId, range: Range<u64>) {
+        self.ranges.entry(client_id).or_default().push(range);
+    }
+
+    /// records that `client_id` spawned something as `net_id`
+    pub(crate) fn claim(&mut self, client_id: ClientId, net_id: NetId) {
+        let highest = self.highest_claimed.entry(client_id).or_insert(net_id.0);
+        *highest = (*highest).max(net_id.0);
+    }
+
+    /// ranges `client_id` hasn't spawned anything from yet
+    pub(crate) fn unused(&self, client_id: ClientId) -> usize {
+        let highest = self.highest_claimed.get(&client_id);
+        self.ranges.get(&client_id).map_or(0, |ranges| {
+            ranges
+                .iter()
+                .filter(|range| highest.map_or(true, |highest| range.start > *highest))
+                .count()
+        })
+    }
+
+    fn forget(&mut self, client_id: ClientId) {
+        self.ranges.remove(&client_id);
+        self.highest_claimed.remove(&client_id);
+    }
+}
+
+/// whether `client_id` may spawn an entity as `net_id`
+pub(crate) fn can_claim(world: &World, client_id: ClientId, net_id: NetId) -> bool {
+    world.resource::<GrantedIds>().contains(client_id, net_id)
+        && !world.resource::<EntityMap>().contains_right(&net_id)
+}
+
+fn grant_range(world: &mut World, client_id: ClientId) {
+    // a client asking for more before using what it has would otherwise burn through the id space
+    if world.resource::<GrantedIds>().unused(client_id) >= MAX_UNUSED_RANGES {
+        println!("client {} asked for more ids without using its last ranges", client_id);
+        return;
+    }
+    let range = world
+        .resource_mut::<NetIdAllocator>()
+        .allocate_range(NET_ID_RANGE_SIZE);
+    world
+        .resource_mut::<GrantedIds>()
+        .insert(client_id, range.clone());
+    world
+        .resource_mut::<Server>()
+        .endpoint_mut()
+        .send_lek_msg(client_id, IdMsgClient::GrantRange(range.start, range.end))
+        .unwrap();
+}
+
+fn grant_initial_range(world: &mut World, mut admitted: Local<ManualEventReader<ClientAdmitted>>) {
+    let client_ids: Vec<ClientId> = admitted
+        .iter(world.resource::<Events<ClientAdmitted>>())
+        .map(|admitted| admitted.client_id)
+        .collect();
+    for client_id in client_ids {
+        grant_range(world, client_id);
+    }
+}
+
+fn forget_granted_ids(
+    mut disconnected: EventReader<ConnectionLostEvent>,
+    mut granted: ResMut<GrantedIds>,
+) {
+    for client in disconnected.iter() {
+        granted.forget(client.id);
+    }
+}
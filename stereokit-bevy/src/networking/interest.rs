@@ -0,0 +1,219 @@
+use crate::networking::delta::DeltaBaselines;
+use crate::networking::entity_client::EntityMsgClient;
+use crate::networking::snapshot::send_entity_snapshot;
+use crate::networking::{Player, SpawnedBy};
+use bevy_ecs::prelude::{Component, Entity, Resource, With, World};
+use bevy_hierarchy::Parent;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::ClientId;
+use bevy_transform::prelude::Transform;
+use leknet::{AdmittedClients, LekServer, NetId, Networked};
+use std::collections::{HashMap, HashSet};
+
+/// turns on interest management on the server, clients only hear about entities relevant to them
+#[derive(Resource, Clone, Debug)]
+pub struct InterestSettings {
+    /// entities closer than this to a client's player are relevant to it
+    pub radius: f32,
+}
+
+/// entities sharing a group with a client's player are relevant to it regardless of distance
+#[derive(Component, Clone, Debug, Default)]
+pub struct InterestGroups(pub HashSet<u32>);
+
+/// the entities each client currently knows about
+#[derive(Resource, Default)]
+pub struct ClientInterest(HashMap<ClientId, HashSet<NetId>>);
+
+impl ClientInterest {
+    pub fn knows(&self, client_id: ClientId, net_id: NetId) -> bool {
+        self.0
+            .get(&client_id)
+            .map_or(false, |known| known.contains(&net_id))
+    }
+
+    pub(crate) fn forget(&mut self, net_id: NetId) {
+        for known in self.0.values_mut() {
+            known.remove(&net_id);
+        }
+    }
+}
+
+/// whether updates about `net_id` should be sent to `client_id`, always true without interest management
+pub fn is_relevant(interest: Option<&ClientInterest>, client_id: ClientId, net_id: NetId) -> bool {
+    interest.map_or(true, |interest| interest.knows(client_id, net_id))
+}
+
+#[derive(Clone)]
+pub(crate) struct Viewer {
+    pub(crate) translation: glam::Vec3,
+    pub(crate) groups: HashSet<u32>,
+}
+
+pub(crate) fn relevant(
+    settings: &InterestSettings,
+    viewer: &Option<Viewer>,
+    translation: Option<glam::Vec3>,
+    groups: Option<&InterestGroups>,
+) -> bool {
+    let viewer = match viewer {
+        // clients without a player yet see everything
+        None => return true,
+        Some(viewer) => viewer,
+    };
+    if let Some(groups) = groups {
+        if !groups.0.is_disjoint(&viewer.groups) {
+            return true;
+        }
+    }
+    match translation {
+        None => true,
+        Some(translation) => translation.distance(viewer.translation) <= settings.radius,
+    }
+}
+
+/// a networked entity as far as interest management cares
+#[derive(Clone)]
+pub(crate) struct InterestEntity {
+    pub(crate) entity: Entity,
+    pub(crate) net_id: NetId,
+    pub(crate) translation: Option<glam::Vec3>,
+    pub(crate) groups: Option<InterestGroups>,
+    pub(crate) spawned_by: Option<ClientId>,
+    pub(crate) parent: Option<Entity>,
+}
+
+/// the root of every entity's networked hierarchy
+pub(crate) fn root_entities(entities: &[InterestEntity]) -> HashMap<Entity, Entity> {
+    let parents: HashMap<Entity, Option<Entity>> = entities
+        .iter()
+        .map(|entity| (entity.entity, entity.parent))
+        .collect();
+    entities
+        .iter()
+        .map(|entity| {
+            let mut root = entity.entity;
+            // bounded so a broken hierarchy can't hang the server
+            for _ in 0..entities.len() {
+                match parents.get(&root).copied().flatten() {
+                    Some(parent) if parents.contains_key(&parent) => root = parent,
+                    _ => break,
+                }
+            }
+            (entity.entity, root)
+        })
+        .collect()
+}
+
+/// the entities `client_id` should know about, children follow their root since clients despawn them along with it
+pub(crate) fn relevant_entities(
+    settings: &InterestSettings,
+    client_id: ClientId,
+    viewer: &Option<Viewer>,
+    entities: &[InterestEntity],
+    roots: &HashMap<Entity, Entity>,
+) -> HashSet<NetId> {
+    let relevant_roots: HashSet<Entity> = entities
+        .iter()
+        .filter(|entity| roots[&entity.entity] == entity.entity)
+        .filter(|entity| {
+            // a client always knows about what it spawned itself
+            entity.spawned_by == Some(client_id)
+                || relevant(settings, viewer, entity.translation, entity.groups.as_ref())
+        })
+        .map(|entity| entity.entity)
+        .collect();
+    entities
+        .iter()
+        .filter(|entity| relevant_roots.contains(&roots[&entity.entity]))
+        .map(|entity| entity.net_id)
+        .collect()
+}
+
+pub(crate) fn update_interest(world: &mut World) {
+    let settings = match world.get_resource::<InterestSettings>() {
+        None => return,
+        Some(settings) => settings.clone(),
+    };
+    world.init_resource::<ClientInterest>();
+    let client_ids = match world.resource::<Server>().get_endpoint() {
+        None => return,
+        Some(endpoint) => world.resource::<AdmittedClients>().of(endpoint),
+    };
+    let entities: Vec<InterestEntity> = world
+        .query_filtered::<(Entity, &NetId, Option<&Transform>, Option<&InterestGroups>, Option<&SpawnedBy>, Option<&Parent>), With<Networked>>()
+        .iter(world)
+        .map(|(entity, net_id, transform, groups, spawned_by, parent)| InterestEntity {
+            entity,
+            net_id: *net_id,
+            translation: transform.map(|transform| transform.translation),
+            groups: groups.cloned(),
+            spawned_by: spawned_by.map(|spawned_by| spawned_by.0),
+            parent: parent.map(|parent| parent.get()),
+        })
+        .collect();
+    let viewers: HashMap<ClientId, Viewer> = world
+        .query_filtered::<(&SpawnedBy, &Transform, Option<&InterestGroups>), With<Player>>()
+        .iter(world)
+        .map(|(spawned_by, transform, groups)| {
+            (
+                spawned_by.0,
+                Viewer {
+                    translation: transform.translation,
+                    groups: groups.map(|groups| groups.0.clone()).unwrap_or_default(),
+                },
+            )
+        })
+        .collect();
+
+    let roots = root_entities(&entities);
+    let spawned_by: HashMap<Entity, Option<ClientId>> = entities
+        .iter()
+        .map(|entity| (entity.entity, entity.spawned_by))
+        .collect();
+
+    let mut interest = world.resource_mut::<ClientInterest>();
+    interest.0.retain(|client_id, _| client_ids.contains(client_id));
+    let mut entered = vec![];
+    let mut left = vec![];
+    for client_id in client_ids.iter() {
+        let viewer = viewers.get(client_id).cloned();
+        let relevant_now = relevant_entities(&settings, *client_id, &viewer, &entities, &roots);
+        let known = interest.0.entry(*client_id).or_default();
+        for entity in entities.iter() {
+            if !relevant_now.contains(&entity.net_id) || !known.insert(entity.net_id) {
+                continue;
+            }
+            // the spawner already has it, unless it went away with a root someone else spawned
+            let root = roots[&entity.entity];
+            if entity.spawned_by == Some(*client_id) && spawned_by[&root] == Some(*client_id) {
+                continue;
+            }
+            entered.push((*client_id, entity.entity));
+        }
+        known.retain(|net_id| {
+            if relevant_now.contains(net_id) {
+                return true;
+            }
+            left.push((*client_id, *net_id));
+            false
+        });
+    }
+
+    // the client forgets the states it decoded once the entity leaves, so it gets a full one when it's back
+    if let Some(mut baselines) = world.get_resource_mut::<DeltaBaselines>() {
+        for (client_id, net_id) in left.iter() {
+            baselines.reset(*client_id, *net_id);
+        }
+    }
+    for (client_id, entity) in entered {
+        send_entity_snapshot(world, client_id, entity);
+    }
+    let mut server = world.resource_mut::<Server>();
+    let endpoint = server.endpoint_mut();
+    for (client_id, net_id) in left {
+        endpoint
+            .send_lek_msg(client_id, EntityMsgClient::Despawned(net_id))
+            .unwrap();
+    }
+}
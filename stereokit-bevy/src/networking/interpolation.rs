@@ -0,0 +1,131 @@
+use crate::networking::ownership_client::Authority;
+use bevy_ecs::prelude::{Component, Entity, Query, Res, Resource, Without, World};
+use crate::networking::runner::ServerTick;
+use leknet::{ServerClock, ServerTime};
+use bevy_transform::prelude::Transform;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// server time and tick an update was sent at, see `ServerClock`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ServerStamp {
+    pub time: f64,
+    pub tick: u64,
+}
+
+impl ServerStamp {
+    pub(crate) fn now(world: &World) -> Self {
+        Self {
+            time: world.resource::<ServerClock>().now(),
+            tick: world.get_resource::<ServerTick>().map_or(0, |tick| tick.0),
+        }
+    }
+}
+
+/// how far behind the server remote entities are rendered, so there's usually a later state to blend towards
+#[derive(Resource, Clone, Debug)]
+pub struct InterpolationSettings {
+    pub delay: Duration,
+    /// how long an entity keeps moving along its last velocity when updates stop arriving
+    pub max_extrapolation: Duration,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(100),
+            max_extrapolation: Duration::from_millis(250),
+        }
+    }
+}
+
+const MAX_BUFFERED: usize = 32;
+
+/// received transforms of a remote entity, oldest first
+#[derive(Component, Default)]
+pub struct TransformBuffer(VecDeque<(f64, Transform)>);
+
+impl TransformBuffer {
+    pub(crate) fn push(&mut self, stamp: ServerStamp, transform: Transform) {
+        // unreliable updates can arrive out of order
+        let index = self
+            .0
+            .iter()
+            .rposition(|(time, _)| *time <= stamp.time)
+            .map_or(0, |index| index + 1);
+        self.0.insert(index, (stamp.time, transform));
+        while self.0.len() > MAX_BUFFERED {
+            self.0.pop_front();
+        }
+    }
+
+    /// the transform at `time`, `None` until something was received
+    pub fn sample(&self, time: f64, max_extrapolation: f64) -> Option<Transform> {
+        let (first_time, first) = *self.0.front()?;
+        if time <= first_time {
+            return Some(first);
+        }
+        for ((from_time, from), (to_time, to)) in self.0.iter().zip(self.0.iter().skip(1)) {
+            if time <= *to_time {
+                let t = ((time - from_time) / (to_time - from_time).max(f64::EPSILON)) as f32;
+                return Some(blend(from, to, t));
+            }
+        }
+        let (last_time, last) = *self.0.back()?;
+        if self.0.len() < 2 {
+            return Some(last);
+        }
+        let (previous_time, previous) = self.0[self.0.len() - 2];
+        let ahead = (time - last_time).min(max_extrapolation);
+        let t = 1.0 + (ahead / (last_time - previous_time).max(f64::EPSILON)) as f32;
+        Some(blend(&previous, &last, t))
+    }
+}
+
+fn blend(from: &Transform, to: &Transform, t: f32) -> Transform {
+    Transform {
+        translation: from.translation.lerp(to.translation, t),
+        rotation: from.rotation.slerp(to.rotation, t.min(1.0)),
+        scale: from.scale.lerp(to.scale, t.min(1.0)),
+    }
+}
+
+pub(crate) fn interpolate_transforms(
+    mut query: Query<(&TransformBuffer, &mut Transform), Without<Authority>>,
+    settings: Res<InterpolationSettings>,
+    server_time: Res<ServerTime>,
+) {
+    if !server_time.is_synced() {
+        return;
+    }
+    let render_time = server_time.now() - settings.delay.as_secs_f64();
+    let max_extrapolation = settings.max_extrapolation.as_secs_f64();
+    for (buffer, mut transform) in query.iter_mut() {
+        if let Some(sampled) = buffer.sample(render_time, max_extrapolation) {
+            if *transform != sampled {
+                *transform = sampled;
+            }
+        }
+    }
+}
+
+/// remote entities get `transform` buffered for interpolation, ones we have authority over are corrected right away
+pub(crate) fn receive_transform(world: &mut World, entity: Entity, transform: Transform, stamp: ServerStamp) {
+    let mut world_entity = match world.get_entity_mut(entity) {
+        None => return,
+        Some(world_entity) => world_entity,
+    };
+    if world_entity.contains::<Authority>() {
+        *world_entity.get_mut().unwrap() = transform;
+        return;
+    }
+    match world_entity.get_mut::<TransformBuffer>() {
+        Some(mut buffer) => buffer.push(stamp, transform),
+        None => {
+            let mut buffer = TransformBuffer::default();
+            buffer.push(stamp, transform);
+            world_entity.insert(buffer);
+        }
+    }
+}
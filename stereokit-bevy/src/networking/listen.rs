@@ -0,0 +1,105 @@
+use crate::networking::runner::advance_tick;
+use crate::networking::StereoKitBevyServerPlugins;
+use bevy_app::{App, AppExit, CoreSet, Plugin, SubApp};
+use bevy_ecs::event::{Events, ManualEventReader};
+use bevy_ecs::prelude::{IntoSystemConfig, Mut, ResMut, Resource, World};
+use bevy_ecs::system::SystemState;
+use bevy_quinnet::client::Client;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::ClientId;
+use leknet::{
+    connect_to_server, start_server, ClientAdmitted, ClientRoles, ListenServerApp, Role, RoleAssignments, RoleToken,
+};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// runs a server inside the client's app and connects the client to it over loopback
+pub struct ListenServer;
+
+impl Plugin for ListenServer {
+    fn build(&self, app: &mut App) {
+        let mut server_app = App::new();
+        server_app.add_plugins(StereoKitBevyServerPlugins);
+        server_app.add_startup_system(start_server);
+        // the client's runner drives the server, so `server_loop` never counts its ticks
+        server_app.add_system(advance_tick.in_base_set(CoreSet::Last));
+        server_app.init_resource::<HostState>();
+        app.insert_sub_app(ListenServerApp, SubApp::new(server_app, sync_host));
+    }
+}
+
+/// the loopback client of the app running the listen server, it's the host whatever identity it gives
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ListenHost(pub ClientId);
+
+#[derive(Resource, Default)]
+struct HostState {
+    connected: bool,
+    /// the one-off secret the loopback client is admitted as host with, until it was
+    token: Option<String>,
+    admitted: ManualEventReader<ClientAdmitted>,
+    app_exit: ManualEventReader<AppExit>,
+}
+
+/// runs before every server update, with access to the client's world
+fn sync_host(client_world: &mut World, server_app: &mut App) {
+    server_app
+        .world
+        .resource_scope(|server_world, mut state: Mut<HostState>| {
+            let exiting = client_world
+                .get_resource::<Events<AppExit>>()
+                .map_or(false, |app_exit| state.app_exit.iter(app_exit).last().is_some());
+            if exiting {
+                // lets the server save the world on the way out
+                server_world.send_event(AppExit);
+            }
+            if state.connected {
+                claim_host(client_world, server_world, &mut *state);
+                return;
+            }
+            // connect once the server is listening, the first server update starts it
+            if server_world.resource::<Server>().get_endpoint().is_none() {
+                return;
+            }
+            // the host proves itself with a secret only this process knows, not with a name anyone could pick
+            let token = host_token();
+            server_world
+                .resource_mut::<RoleAssignments>()
+                .tokens
+                .insert(token.clone(), Role::Host);
+            client_world.insert_resource(RoleToken(Some(token.clone())));
+            state.token = Some(token);
+            let mut system_state: SystemState<ResMut<Client>> = SystemState::new(client_world);
+            connect_to_server(system_state.get_mut(client_world));
+            state.connected = true;
+        });
+}
+
+/// once the loopback client is admitted with the token, the host role belongs to its `ClientId` and the token is spent
+fn claim_host(client_world: &mut World, server_world: &mut World, state: &mut HostState) {
+    let token = match state.token.clone() {
+        None => return,
+        Some(token) => token,
+    };
+    let host = state
+        .admitted
+        .iter(server_world.resource::<Events<ClientAdmitted>>())
+        .map(|admitted| admitted.client_id)
+        .find(|client_id| server_world.resource::<ClientRoles>().get(*client_id) == Role::Host);
+    let host = match host {
+        None => return,
+        Some(host) => host,
+    };
+    println!("client {} is the host", host);
+    server_world.resource_mut::<RoleAssignments>().tokens.remove(&token);
+    server_world.insert_resource(ListenHost(host));
+    client_world.insert_resource(RoleToken(None));
+    state.token = None;
+}
+
+/// a fresh random secret, `RandomState` is seeded from the OS
+fn host_token() -> String {
+    (0..2)
+        .map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+        .collect()
+}
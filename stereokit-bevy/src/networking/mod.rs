@@ -1,35 +1,104 @@
 use crate::{model_draw, ModelInfo};
-use bevy_app::{App, Plugin, PluginGroup, PluginGroupBuilder};
-use bevy_ecs::prelude::{Component, Schedules};
+use bevy_app::{App, CoreSet, Plugin, PluginGroup, PluginGroupBuilder};
+use bevy_ecs::prelude::{Component, IntoSystemConfig, Resource, Schedules};
+use bevy_quinnet::shared::ClientId;
 use bevy_transform::prelude::Transform;
 use bevy_transform::systems::{propagate_transforms, sync_simple_transforms};
+use bevy_transform::TransformSystem;
 use leknet::{ClientMessage, LeknetClient, LeknetServer, ServerMessage};
 use serde::{Deserialize, Serialize};
 use stereokit::{Color128, RenderLayer, Settings};
 
+mod admin_client;
+mod admin_server;
+mod component_client;
+mod component_server;
+mod delta;
+mod entity_client;
+mod entity_server;
+mod hierarchy_client;
+mod hierarchy_server;
+mod id_client;
+mod id_server;
+mod interest;
+mod interpolation;
+mod listen;
 mod model_client;
 mod model_server;
+mod ownership_client;
+mod ownership_server;
+mod pending;
+mod permissions;
+mod persistence;
 #[cfg(test)]
 mod tests;
 pub mod player_client;
 mod player_server;
+mod prediction;
+mod quantize;
+mod replicated_resource;
+mod replication;
+mod resource_client;
+mod resource_server;
+mod runner;
+mod snapshot;
+mod validation;
+
+pub use admin_client::{AdminOutput, ServerNotice};
+pub use admin_server::{is_muted, run_admin_command, AdminCommand, AdminMsgServer, AdminSettings, MutedClients};
+pub use component_server::ComponentMsgServer;
+pub use delta::ModelDelta;
+pub use hierarchy_server::HierarchyMsgServer;
+pub use id_client::NetIdPool;
+pub use id_server::GrantedIds;
+pub use interest::{is_relevant, ClientInterest, InterestGroups, InterestSettings};
+pub use interpolation::{InterpolationSettings, ServerStamp, TransformBuffer};
+pub use listen::{ListenHost, ListenServer};
+pub use model_server::ModelMsgServer;
+pub use ownership_client::{Authority, ReleaseAuthority, RequestAuthority};
+pub use ownership_server::{has_authority, Owner};
+pub use pending::PendingUpdateSettings;
+pub use permissions::{is_permitted, Permission, PermissionTable};
+pub use player_server::PlayerMsgServer;
+pub use prediction::PredictionHistory;
+pub use quantize::{CompactTransform, QuantizationSettings, TransformEncoding, WireModelData, WireTransform};
+pub use persistence::{save_world, WorldPersistence, WORLD_SAVE_VERSION};
+pub use replicated_resource::{ResourceChangeRequest, ResourceRegistry};
+pub use replication::{ReplicateAppExt, ReplicationRegistry};
+pub use runner::{ServerTick, ServerTickRate};
+pub use validation::{ValidateAppExt, Validation, ValidationLimits};
 
 #[derive(Clone, Copy, Component, Debug, Serialize, Deserialize)]
 pub struct Player;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ModelData {
-    model_info: ModelInfo,
-    transform: Transform,
-    color128: Color128,
-    render_layer: RenderLayer,
+    pub model_info: ModelInfo,
+    pub transform: Transform,
+    pub color128: Color128,
+    pub render_layer: RenderLayer,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ModelData2 {
-    transform: Transform,
-    color128: Color128,
-    render_layer: RenderLayer,
+    pub transform: Transform,
+    pub color128: Color128,
+    pub render_layer: RenderLayer,
+}
+
+/// the client that asked the server to spawn this entity, only present on the server
+#[derive(Clone, Copy, Component, Debug)]
+pub struct SpawnedBy(pub ClientId);
+
+/// how many `NetId`s a client is granted at a time
+pub const NET_ID_RANGE_SIZE: u64 = 1024;
+
+/// spectators receive everything replicated but never spawn a `LocalPlayer`, so nobody else sees them
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClientMode {
+    #[default]
+    Participant,
+    Spectator,
 }
 
 #[derive(Component)]
@@ -48,6 +117,21 @@ impl Plugin for StereoKitBevyClient {
     fn build(&self, app: &mut App) {
         model_client::ModelMsgClient::add_plugin_client(app);
         player_client::PlayerMsgClient::add_plugin_client(app);
+        component_client::ComponentMsgClient::add_plugin_client(app);
+        entity_client::EntityMsgClient::add_plugin_client(app);
+        hierarchy_client::HierarchyMsgClient::add_plugin_client(app);
+        resource_client::ResourceMsgClient::add_plugin_client(app);
+        ownership_client::OwnershipMsgClient::add_plugin_client(app);
+        id_client::IdMsgClient::add_plugin_client(app);
+        admin_client::AdminMsgClient::add_plugin_client(app);
+        app.init_resource::<pending::PendingUpdates>();
+        app.add_system(pending::flush_pending_updates);
+        app.init_resource::<interpolation::InterpolationSettings>();
+        app.add_system(
+            interpolation::interpolate_transforms
+                .in_base_set(CoreSet::PostUpdate)
+                .before(TransformSystem::TransformPropagate),
+        );
         fn stereokit_loop(mut app: App) {
             Settings::default()
                 .init()
@@ -64,17 +148,34 @@ impl Plugin for StereoKitBevyServer {
     fn build(&self, app: &mut App) {
         model_server::ModelMsgServer::add_plugin_server(app);
         player_server::PlayerMsgServer::add_plugin_server(app);
-        fn server_loop(mut app: App) {
-            loop {
-                app.update()
-            }
-        }
-        app.set_runner(server_loop);
+        component_server::ComponentMsgServer::add_plugin_server(app);
+        entity_server::EntityMsgServer::add_plugin_server(app);
+        hierarchy_server::HierarchyMsgServer::add_plugin_server(app);
+        resource_server::ResourceMsgServer::add_plugin_server(app);
+        ownership_server::OwnershipMsgServer::add_plugin_server(app);
+        id_server::IdMsgServer::add_plugin_server(app);
+        admin_server::AdminMsgServer::add_plugin_server(app);
+        app.init_resource::<permissions::PermissionTable>();
+        app.init_resource::<pending::PendingChanges>();
+        app.add_system(pending::expire_pending_changes);
+        app.init_resource::<validation::ValidationLimits>();
+        app.add_validator(validation::validate_model_msg);
+        app.add_validator(validation::validate_player_msg);
+        app.add_system(snapshot::new_client_snapshot);
+        app.add_system(interest::update_interest);
+        app.add_startup_system(persistence::load_world);
+        app.add_system(persistence::autosave_world);
+        app.add_system(persistence::save_world_on_exit.in_base_set(CoreSet::Last));
+        app.init_resource::<runner::ServerTickRate>();
+        app.init_resource::<runner::ServerTick>();
+        app.set_runner(runner::server_loop);
     }
 }
 
 pub struct StereoKitBevyClientPlugins;
 pub struct StereoKitBevyServerPlugins;
+/// the client plugins plus a `ListenServer`, so this app hosts the session it plays in
+pub struct StereoKitBevyHostPlugins;
 
 impl PluginGroup for StereoKitBevyClientPlugins {
     fn build(self) -> PluginGroupBuilder {
@@ -97,7 +198,15 @@ impl PluginGroup for StereoKitBevyServerPlugins {
         PluginGroupBuilder::start::<Self>()
             .add(StereoKitBevyServer)
             .add(LeknetServer)
+            .add(bevy_hierarchy::HierarchyPlugin)
+            .add(bevy_core::TypeRegistrationPlugin)
             .add(bevy_time::TimePlugin)
             .add(bevy_quinnet::server::QuinnetServerPlugin::default())
     }
 }
+
+impl PluginGroup for StereoKitBevyHostPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        StereoKitBevyClientPlugins.build().add(ListenServer)
+    }
+}
@@ -1,28 +1,41 @@
 use crate::networking::model_server::ModelMsgServer;
-use crate::networking::{IgnoreModelAdd, IgnoreModelChanged, ModelData, ModelData2};
+use crate::networking::ownership_client::Authority;
+use crate::networking::pending::PendingUpdates;
+use crate::networking::prediction::PredictionHistory;
+use crate::networking::quantize::{
+    model_from_wire, QuantizationSettings, TransformEncoding, WireModelData, WireTransform,
+};
+use crate::networking::delta::{ModelDelta, ReceivedStates};
+use crate::networking::id_client::NetIdPool;
+use crate::networking::interpolation::{receive_transform, ServerStamp};
+use crate::networking::{IgnoreModelAdd, ModelData, ModelData2};
 use crate::{ModelBundle, ModelInfo};
 use bevy_app::App;
 use bevy_ecs::prelude::{
-    Added, Changed, Commands, Entity, NonSend, Or, Query, Res, ResMut, With, World,
+    Changed, Commands, Entity, NonSend, Or, Query, Res, ResMut, With, World,
 };
 use bevy_ecs::query::Without;
 use bevy_ecs::system::SystemState;
+use bevy_hierarchy::DespawnRecursiveExt;
 use bevy_quinnet::client::Client;
 use bevy_quinnet::shared::channel::ChannelType;
-use bevy_quinnet::shared::ClientId;
 use bevy_transform::prelude::Transform;
-use leknet::{
-    ClientEntity, ClientMessage, EntityMap, LekClient, Networked, ServerEntity, TypeName,
-};
+use leknet::{ClientMessage, EntityMap, LekClient, NetId, Networked, TypeName};
 use serde::{Deserialize, Serialize};
 use stereokit::{Color128, Material, Model, RenderLayer, SkDraw, StereoKitMultiThread};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ModelMsgClient {
-    ModelAdded(ServerEntity, ModelData),
-    ModelChanged(ServerEntity, ModelData2),
-    EntityMap(ServerEntity, ClientEntity),
-    GetAllModelData(ClientId),
+    ModelAdded(NetId, ModelData),
+    ModelChanged(NetId, WireModelData, ServerStamp),
+    /// the server refused to spawn this model
+    ModelRejected(NetId),
+    /// the server spawned our model, but only after clamping it to this
+    ModelCorrected(NetId, ModelData),
+    /// the server's state after applying our change with this sequence number
+    Ack(NetId, u32, ModelData2),
+    /// a change relative to the state with the baseline sequence number, or a full state without one
+    ModelDelta(NetId, u32, Option<u32>, ModelDelta, ServerStamp),
 }
 
 impl TypeName for ModelMsgClient {
@@ -34,18 +47,28 @@ impl TypeName for ModelMsgClient {
 impl ClientMessage for ModelMsgClient {
     fn client(self, world: &mut World) {
         match self {
-            ModelMsgClient::ModelAdded(server_entity, model_data) => {
-                model_added_msg(world, server_entity, model_data)
+            ModelMsgClient::ModelAdded(net_id, model_data) => {
+                model_added_msg(world, net_id, model_data)
+            }
+            ModelMsgClient::ModelChanged(net_id, wire_model_data, stamp) => {
+                let model_data = model_from_wire(world, &wire_model_data);
+                model_changed_msg(world, net_id, model_data, stamp)
             }
-            ModelMsgClient::ModelChanged(server_entity, model_data) => {
-                model_changed_msg(world, server_entity, model_data)
+            ModelMsgClient::Ack(net_id, seq, model_data) => ack_msg(world, net_id, seq, model_data),
+            ModelMsgClient::ModelDelta(net_id, seq, baseline, delta, stamp) => {
+                model_delta_msg(world, net_id, seq, baseline, delta, stamp)
             }
-            ModelMsgClient::EntityMap(server_entity, client_entity) => {
-                let mut system_state: SystemState<ResMut<EntityMap>> = SystemState::new(world);
-                let mut entity_map: ResMut<EntityMap> = system_state.get_mut(world);
-                entity_map.0.insert(client_entity, server_entity);
+            ModelMsgClient::ModelCorrected(net_id, model_data) => {
+                model_corrected_msg(world, net_id, model_data)
+            }
+            ModelMsgClient::ModelRejected(net_id) => {
+                let entity = world.resource_mut::<EntityMap>().remove_by_right(&net_id);
+                if let Some((entity, _)) = entity {
+                    if let Some(world_entity) = world.get_entity_mut(entity) {
+                        world_entity.despawn_recursive();
+                    }
+                }
             }
-            ModelMsgClient::GetAllModelData(client_id) => get_all_model_data_msg(world, client_id),
         }
     }
 
@@ -58,116 +81,146 @@ impl ClientMessage for ModelMsgClient {
     fn channel_type(&self) -> ChannelType {
         match self {
             ModelMsgClient::ModelAdded(_, _) => ChannelType::OrderedReliable,
-            ModelMsgClient::ModelChanged(_, _) => ChannelType::Unreliable,
-            ModelMsgClient::EntityMap(_, _) => ChannelType::OrderedReliable,
-            ModelMsgClient::GetAllModelData(_) => ChannelType::OrderedReliable,
+            ModelMsgClient::ModelChanged(_, _, _) => ChannelType::Unreliable,
+            ModelMsgClient::ModelRejected(_) => ChannelType::OrderedReliable,
+            ModelMsgClient::ModelCorrected(_, _) => ChannelType::OrderedReliable,
+            ModelMsgClient::Ack(_, _, _) => ChannelType::Unreliable,
+            ModelMsgClient::ModelDelta(_, _, _, _, _) => ChannelType::Unreliable,
         }
     }
 
     fn plugin(app: &mut App) {
+        app.init_resource::<ReceivedStates>();
         app.add_system(model_added);
         app.add_system(model_changed);
     }
 }
 
-fn get_all_model_data_msg(world: &mut World, client_id: ClientId) {
-    let mut system_state: SystemState<(
-        Query<
-            (Entity, &ModelInfo, &Transform, &Color128, &RenderLayer),
-            (With<Networked>, Without<IgnoreModelChanged>),
-        >,
-        ResMut<Client>,
-        Res<EntityMap>,
-    )> = SystemState::new(world);
-    let (query, mut client, entity_map) = system_state.get_mut(world);
-    let mut client: ResMut<Client> = client;
-    let entity_map: Res<EntityMap> = entity_map;
-    let mut models = vec![];
-    for (entity, model_info, transform, color128, render_layer) in query.iter() {
-        let server_entity = match entity_map.get_by_left(&ClientEntity(entity)) {
-            None => continue,
-            Some(server_entity) => server_entity.clone(),
-        };
-        models.push((
-            server_entity,
-            ModelData {
-                model_info: model_info.clone(),
-                transform: *transform,
-                color128: *color128,
-                render_layer: *render_layer,
-            },
-        ))
-    }
-    client
-        .connection_mut()
-        .send_lek_msg(ModelMsgServer::AllModelData(client_id, models))
-        .unwrap();
-}
-
-fn model_changed_msg(world: &mut World, server_entity: ServerEntity, model_data: ModelData2) {
+pub(crate) fn model_changed_msg(world: &mut World, net_id: NetId, model_data: ModelData2, stamp: ServerStamp) {
     let mut client_entity = None;
     {
         let mut system_state: SystemState<ResMut<EntityMap>> = SystemState::new(world);
         let mut entity_map = system_state.get_mut(world);
-        client_entity = entity_map.get_by_right(&server_entity).map(|a| a.clone());
+        client_entity = entity_map.get_by_right(&net_id).map(|a| a.clone());
     }
     if let Some(client_entity) = client_entity {
-        let mut world_entity = world.entity_mut(client_entity.0);
+        let mut world_entity = world.entity_mut(client_entity);
         match model_data {
             ModelData2 {
                 transform,
                 color128,
                 render_layer,
             } => {
-                *world_entity.get_mut().unwrap() = transform;
                 *world_entity.get_mut().unwrap() = color128;
                 *world_entity.get_mut().unwrap() = render_layer;
+                receive_transform(world, client_entity, transform, stamp);
             }
         }
+    } else {
+        world
+            .resource_mut::<PendingUpdates>()
+            .defer_model(net_id, model_data, stamp);
     }
 }
 
-fn model_added_msg(world: &mut World, server_entity: ServerEntity, model_data: ModelData) {
+fn model_delta_msg(
+    world: &mut World,
+    net_id: NetId,
+    seq: u32,
+    baseline: Option<u32>,
+    delta: ModelDelta,
+    stamp: ServerStamp,
+) {
+    let quantization = world.get_resource::<QuantizationSettings>().copied().unwrap_or_default();
+    let state = world
+        .resource_mut::<ReceivedStates>()
+        .decode(net_id, seq, baseline, &delta, &quantization);
+    let reply = match state {
+        Some(_) => ModelMsgServer::DeltaAck(net_id, seq),
+        None => ModelMsgServer::ResendFull(net_id),
+    };
+    if let Some(connection) = world.resource_mut::<Client>().get_connection_mut() {
+        connection.send_lek_msg(reply).unwrap();
+    }
+    if let Some(state) = state {
+        model_changed_msg(world, net_id, state, stamp);
+    }
+}
+
+fn model_added_msg(world: &mut World, net_id: NetId, model_data: ModelData) {
+    if world.resource::<EntityMap>().contains_right(&net_id) {
+        return;
+    }
     let mut system_state: SystemState<(ResMut<EntityMap>, Commands, NonSend<SkDraw>)> =
         SystemState::new(world);
     let (entity_map, commands, sk) = system_state.get_mut(world);
     let mut entity_map: ResMut<EntityMap> = entity_map;
     let mut commands: Commands = commands;
     let sk: NonSend<SkDraw> = sk;
-    let model = match model_data.model_info.clone() {
+    let model = create_model(&sk, &model_data.model_info);
+    let client_entity = commands
+        .spawn(ModelBundle::new(
+            model,
+            model_data.model_info,
+            model_data.transform,
+            model_data.color128,
+            model_data.render_layer,
+        ))
+        .insert((Networked, IgnoreModelAdd))
+        .insert(net_id)
+        .id();
+    entity_map.insert(client_entity, net_id);
+    system_state.apply(world);
+}
+
+fn create_model(sk: &SkDraw, model_info: &ModelInfo) -> Model {
+    match model_info {
         ModelInfo::Mem { .. } => {
             todo!()
         }
-        ModelInfo::Cube(size) => sk.model_create_mesh(sk.mesh_gen_cube(size, 1), Material::DEFAULT),
+        ModelInfo::Cube(size) => sk.model_create_mesh(sk.mesh_gen_cube(*size, 1), Material::DEFAULT),
+    }
+}
+
+fn model_corrected_msg(world: &mut World, net_id: NetId, model_data: ModelData) {
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
     };
-    let client_entity = ClientEntity(
-        commands
-            .spawn(ModelBundle::new(
-                model,
-                model_data.model_info,
-                model_data.transform,
-                model_data.color128,
-                model_data.render_layer,
-            ))
-            .insert(IgnoreModelAdd)
-            .id(),
-    );
-    entity_map.insert(client_entity, server_entity);
-    system_state.apply(world);
+    let model = create_model(&world.non_send_resource::<SkDraw>(), &model_data.model_info);
+    if let Some(mut world_entity) = world.get_entity_mut(entity) {
+        world_entity.insert((
+            model,
+            model_data.model_info,
+            model_data.transform,
+            model_data.color128,
+            model_data.render_layer,
+        ));
+    }
 }
 
 fn model_added(
     query: Query<
         (Entity, &ModelInfo, &Transform, &Color128, &RenderLayer),
-        (Added<Networked>, Without<IgnoreModelAdd>),
+        (With<Networked>, Without<NetId>, Without<IgnoreModelAdd>),
     >,
     mut client: ResMut<Client>,
+    mut pool: ResMut<NetIdPool>,
+    mut entity_map: ResMut<EntityMap>,
+    mut commands: Commands,
 ) {
     if let Some(connection) = client.get_connection_mut() {
         for (entity, model_info, transform, color128, render_layer) in query.iter() {
+            // without a granted id the model waits until the server hands out a range
+            let net_id = match pool.next() {
+                None => return,
+                Some(net_id) => net_id,
+            };
+            entity_map.insert(entity, net_id);
+            commands.entity(entity).insert(net_id);
             connection
                 .send_lek_msg(ModelMsgServer::ModelAdded(
-                    ClientEntity(entity),
+                    net_id,
                     ModelData {
                         model_info: model_info.clone(),
                         transform: *transform,
@@ -181,31 +234,78 @@ fn model_added(
 }
 
 fn model_changed(
-    query: Query<
-        (Entity, &ModelInfo, &Transform, &Color128, &RenderLayer),
+    mut query: Query<
+        (Entity, &Transform, &Color128, &RenderLayer, Option<&mut PredictionHistory>),
         (
             Or<(Changed<Transform>, Changed<Color128>, Changed<RenderLayer>)>,
-            Without<IgnoreModelAdd>,
+            With<Authority>,
             With<Networked>,
         ),
     >,
     mut client: ResMut<Client>,
     entity_map: Res<EntityMap>,
+    encoding: Option<Res<TransformEncoding>>,
+    quantization: Option<Res<QuantizationSettings>>,
+    mut commands: Commands,
 ) {
+    let encoding = encoding.map_or(TransformEncoding::default(), |encoding| *encoding);
+    let quantization = quantization.map_or(QuantizationSettings::default(), |quantization| *quantization);
     if let Some(connection) = client.get_connection_mut() {
-        for (entity, _, transform, color128, render_layer) in query.iter() {
-            if let Some(server_entity) = entity_map.get_by_left(&ClientEntity(entity)) {
+        for (entity, transform, color128, render_layer, history) in query.iter_mut() {
+            if let Some(net_id) = entity_map.get_by_left(&entity) {
+                let wire_transform = WireTransform::encode(transform, encoding, &quantization);
+                // the change is already applied locally, the server confirms or corrects it with an ack.
+                // what the server will see is predicted, so quantization alone doesn't count as a correction
+                let sent = wire_transform.decode(&quantization);
+                let seq = match history {
+                    Some(mut history) => history.record(sent),
+                    None => {
+                        let mut history = PredictionHistory::default();
+                        let seq = history.record(sent);
+                        commands.entity(entity).insert(history);
+                        seq
+                    }
+                };
                 connection
                     .send_lek_msg(ModelMsgServer::ModelChanged(
-                        *server_entity,
-                        ModelData2 {
-                            transform: *transform,
+                        *net_id,
+                        WireModelData {
+                            transform: wire_transform,
                             color128: *color128,
                             render_layer: *render_layer,
                         },
+                        seq,
                     ))
                     .unwrap()
             }
         }
     }
 }
+
+fn ack_msg(world: &mut World, net_id: NetId, seq: u32, model_data: ModelData2) {
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    let mut world_entity = match world.get_entity_mut(entity) {
+        None => return,
+        Some(world_entity) => world_entity,
+    };
+    // without authority the entity follows the server's relayed updates instead
+    if !world_entity.contains::<Authority>() {
+        return;
+    }
+    let current = match world_entity.get::<Transform>() {
+        None => return,
+        Some(transform) => *transform,
+    };
+    let reconciled = match world_entity.get_mut::<PredictionHistory>() {
+        None => return,
+        Some(mut history) => history.reconcile(seq, &model_data.transform, &current),
+    };
+    if let Some(reconciled) = reconciled {
+        *world_entity.get_mut::<Transform>().unwrap() = reconciled;
+        *world_entity.get_mut().unwrap() = model_data.color128;
+        *world_entity.get_mut().unwrap() = model_data.render_layer;
+    }
+}
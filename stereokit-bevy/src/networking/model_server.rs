@@ -1,19 +1,36 @@
+use crate::networking::delta::{forget_client_baselines, DeltaBaselines};
+use crate::networking::id_server::{can_claim, GrantedIds};
 use crate::networking::model_client::ModelMsgClient;
-use crate::networking::{ModelData, ModelData2};
+use crate::networking::interest::{is_relevant, ClientInterest};
+use crate::networking::interpolation::ServerStamp;
+use crate::networking::ownership_server::{has_authority, Owner};
+use crate::networking::pending::{apply_pending_change, defer_change, PendingChange};
+use crate::networking::permissions::{is_permitted, Permission};
+use crate::networking::prediction::{is_newer, LastAppliedSeq};
+use crate::networking::quantize::{model_from_wire, quantized, QuantizationSettings, TransformEncoding, WireModelData};
+use crate::networking::validation::{validate, Validation};
+use crate::networking::{ModelData, ModelData2, SpawnedBy};
+use crate::ModelInfo;
 use bevy_app::App;
-use bevy_ecs::prelude::{Commands, EventReader, ResMut, World};
+use bevy_ecs::prelude::{Commands, Res, ResMut, World};
+use bevy_transform::prelude::Transform;
+use stereokit::{Color128, RenderLayer};
 use bevy_ecs::system::SystemState;
-use bevy_quinnet::server::{ConnectionEvent, Server};
+use bevy_quinnet::server::Server;
 use bevy_quinnet::shared::channel::ChannelType;
 use bevy_quinnet::shared::ClientId;
-use leknet::{ClientEntity, LekServer, ServerEntity, ServerMessage, TypeName};
+use leknet::{AdmittedClients, EntityMap, LekServer, NetId, Networked, ServerMessage, TypeName};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ModelMsgServer {
-    ModelAdded(ClientEntity, ModelData),
-    ModelChanged(ServerEntity, ModelData2),
-    AllModelData(ClientId, Vec<(ServerEntity, ModelData)>),
+    ModelAdded(NetId, ModelData),
+    /// the client's sequence number for this change is acknowledged with `ModelMsgClient::Ack`
+    ModelChanged(NetId, WireModelData, u32),
+    /// the client decoded the `ModelMsgClient::ModelDelta` with this sequence number, later ones can build on it
+    DeltaAck(NetId, u32),
+    /// the client couldn't decode a delta, so the next update is sent in full
+    ResendFull(NetId),
 }
 
 impl TypeName for ModelMsgServer {
@@ -23,27 +40,53 @@ impl TypeName for ModelMsgServer {
 }
 
 impl ServerMessage for ModelMsgServer {
-    fn server(self, world: &mut World, client_id: ClientId) {
-        match self {
-            ModelMsgServer::ModelAdded(client_entity, model_data) => {
-                model_added_msg(world, client_id, client_entity, model_data)
-            }
-            ModelMsgServer::ModelChanged(server_entity, model_data) => {
-                model_changed_msg(world, client_id, server_entity, model_data)
+    fn server(mut self, world: &mut World, client_id: ClientId) {
+        let validation = validate(world, client_id, &mut self);
+        if validation == Validation::Reject {
+            match self {
+                ModelMsgServer::ModelAdded(net_id, _) => reject_model(world, client_id, net_id),
+                ModelMsgServer::ModelChanged(net_id, _, seq) => send_ack(world, client_id, net_id, seq),
+                ModelMsgServer::DeltaAck(_, _) => {}
+                ModelMsgServer::ResendFull(_) => {}
             }
-            ModelMsgServer::AllModelData(client_id, all_model_data) => {
-                let mut endpoint: SystemState<ResMut<Server>> = SystemState::new(world);
-                let mut endpoint = endpoint.get_mut(world);
-                let endpoint = endpoint.endpoint_mut();
-                for (entity, model_data) in all_model_data {
-                    endpoint
-                        .send_lek_msg(
-                            client_id.clone(),
-                            ModelMsgClient::ModelAdded(entity, model_data),
-                        )
+            return;
+        }
+        match self {
+            ModelMsgServer::ModelAdded(net_id, model_data) => {
+                let clamped = (validation == Validation::Clamped).then(|| model_data.clone());
+                let added = model_added_msg(world, client_id, net_id, model_data);
+                // the sender still has the model it asked for, tell it what was actually spawned
+                if let (true, Some(model_data)) = (added, clamped) {
+                    world
+                        .resource_mut::<Server>()
+                        .endpoint_mut()
+                        .send_lek_msg(client_id, ModelMsgClient::ModelCorrected(net_id, model_data))
                         .unwrap();
                 }
             }
+            ModelMsgServer::ModelChanged(net_id, wire_model_data, seq) => {
+                let model_data = model_from_wire(world, &wire_model_data);
+                // the change overtook the model it belongs to, it's applied once the model is added
+                if defer_change(world, client_id, net_id, PendingChange::Model(model_data.clone(), seq)) {
+                    return;
+                }
+                // unreliable changes can arrive out of order, an older one would undo a newer one
+                if !accept_seq(world, client_id, net_id, seq) {
+                    return;
+                }
+                model_changed_msg(world, client_id, net_id, model_data);
+                send_ack(world, client_id, net_id, seq);
+            }
+            ModelMsgServer::DeltaAck(net_id, seq) => {
+                world
+                    .resource_mut::<DeltaBaselines>()
+                    .ack(client_id, net_id, seq);
+            }
+            ModelMsgServer::ResendFull(net_id) => {
+                world
+                    .resource_mut::<DeltaBaselines>()
+                    .reset(client_id, net_id);
+            }
         }
     }
 
@@ -56,81 +99,174 @@ impl ServerMessage for ModelMsgServer {
     fn channel_type(&self) -> ChannelType {
         match self {
             ModelMsgServer::ModelAdded(_, _) => ChannelType::OrderedReliable,
-            ModelMsgServer::ModelChanged(_, _) => ChannelType::Unreliable,
-            ModelMsgServer::AllModelData(_, _) => ChannelType::OrderedReliable,
+            ModelMsgServer::ModelChanged(_, _, _) => ChannelType::Unreliable,
+            ModelMsgServer::DeltaAck(_, _) => ChannelType::Unreliable,
+            ModelMsgServer::ResendFull(_) => ChannelType::OrderedReliable,
         }
     }
 
     fn plugin(app: &mut App) {
-        app.add_system(new_client_connected);
+        app.init_resource::<DeltaBaselines>();
+        app.add_system(forget_client_baselines);
     }
 }
 
-fn model_changed_msg(world: &mut World, client_id: ClientId, server_entity: ServerEntity, model_data: ModelData2) {
-    let mut system_state: SystemState<ResMut<Server>> = SystemState::new(world);
-    let mut server = system_state.get_mut(world);
+pub(crate) fn model_changed_msg(world: &mut World, client_id: ClientId, net_id: NetId, model_data: ModelData2) {
+    if !has_authority(world, client_id, net_id) {
+        return;
+    }
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    // players and other non models have no color or render layer to overwrite
+    if world.get::<ModelInfo>(entity).is_none() {
+        return;
+    }
+    // keep what the other clients will decode, so the server's state doesn't drift from theirs
+    let model_data = ModelData2 {
+        transform: quantized(world, &model_data.transform),
+        ..model_data
+    };
+    if let Some(mut world_entity) = world.get_entity_mut(entity) {
+        if let Some(mut transform) = world_entity.get_mut::<Transform>() {
+            *transform = model_data.transform;
+        }
+        if let Some(mut color128) = world_entity.get_mut::<Color128>() {
+            *color128 = model_data.color128;
+        }
+        if let Some(mut render_layer) = world_entity.get_mut::<RenderLayer>() {
+            *render_layer = model_data.render_layer;
+        }
+    }
+    let stamp = ServerStamp::now(world);
+    let encoding = world.get_resource::<TransformEncoding>().copied().unwrap_or_default();
+    let quantization = world.get_resource::<QuantizationSettings>().copied().unwrap_or_default();
+    let mut system_state: SystemState<(
+        ResMut<Server>,
+        ResMut<DeltaBaselines>,
+        Option<Res<ClientInterest>>,
+        Res<AdmittedClients>,
+    )> = SystemState::new(world);
+    let (mut server, mut baselines, interest, admitted) = system_state.get_mut(world);
     let endpoint = server.endpoint_mut();
-    for client_id2 in endpoint.clients() {
-        if client_id2 == client_id {
+    for client_id2 in admitted.of(endpoint) {
+        if client_id2 == client_id || !is_relevant(interest.as_deref(), client_id2, net_id) {
             continue;
         }
+        // only what changed since the state this client last confirmed goes over the wire
+        let (seq, baseline, delta) = baselines.encode(client_id2, net_id, &model_data, encoding, &quantization);
         endpoint
             .send_lek_msg(
                 client_id2.clone(),
-                ModelMsgClient::ModelChanged(
-                    server_entity,
-                    model_data.clone(),
-                ),
+                ModelMsgClient::ModelDelta(net_id, seq, baseline, delta, stamp),
             )
             .unwrap();
     }
 }
 
-fn model_added_msg(world: &mut World, client_id: ClientId, client_entity: ClientEntity, model_data: ModelData) {
-    let mut system_state: SystemState<(ResMut<Server>, Commands)> =
-        SystemState::new(world);
-    let (mut server, mut commands) = system_state.get_mut(world);
+/// records `seq` as the latest change to `net_id` unless a newer one was already applied
+pub(crate) fn accept_seq(world: &mut World, client_id: ClientId, net_id: NetId, seq: u32) -> bool {
+    if !has_authority(world, client_id, net_id) {
+        return false;
+    }
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return false,
+        Some(entity) => *entity,
+    };
+    let mut world_entity = match world.get_entity_mut(entity) {
+        None => return false,
+        Some(world_entity) => world_entity,
+    };
+    if let Some(last) = world_entity.get::<LastAppliedSeq>() {
+        if !is_newer(seq, last.0) {
+            return false;
+        }
+    }
+    world_entity.insert(LastAppliedSeq(seq));
+    true
+}
+
+/// tells the sender which state the server ended up with after its change `seq`, so it can reconcile
+pub(crate) fn send_ack(world: &mut World, client_id: ClientId, net_id: NetId, seq: u32) {
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    let world_entity = match world.get_entity(entity) {
+        None => return,
+        Some(world_entity) => world_entity,
+    };
+    let model_data = match (
+        world_entity.get::<Transform>(),
+        world_entity.get::<Color128>(),
+        world_entity.get::<RenderLayer>(),
+    ) {
+        (Some(transform), Some(color128), Some(render_layer)) => ModelData2 {
+            transform: *transform,
+            color128: *color128,
+            render_layer: *render_layer,
+        },
+        _ => return,
+    };
+    world
+        .resource_mut::<Server>()
+        .endpoint_mut()
+        .send_lek_msg(client_id, ModelMsgClient::Ack(net_id, seq, model_data))
+        .unwrap();
+}
+
+fn reject_model(world: &mut World, client_id: ClientId, net_id: NetId) {
+    world
+        .resource_mut::<Server>()
+        .endpoint_mut()
+        .send_lek_msg(client_id, ModelMsgClient::ModelRejected(net_id))
+        .unwrap();
+}
+
+/// spawns the model unless the sender may not, returning whether it was spawned
+fn model_added_msg(world: &mut World, client_id: ClientId, net_id: NetId, model_data: ModelData) -> bool {
+    if !can_claim(world, client_id, net_id) || !is_permitted(world, client_id, Permission::SpawnModels) {
+        reject_model(world, client_id, net_id);
+        return false;
+    }
+    world.resource_mut::<GrantedIds>().claim(client_id, net_id);
+    let mut system_state: SystemState<(
+        ResMut<Server>,
+        ResMut<EntityMap>,
+        Option<Res<ClientInterest>>,
+        Res<AdmittedClients>,
+        Commands,
+    )> = SystemState::new(world);
+    let (mut server, mut entity_map, interest, admitted, mut commands) = system_state.get_mut(world);
     let mut server: ResMut<Server> = server;
     let mut commands: Commands = commands;
-    let server_entity = ServerEntity(commands.spawn_empty().id());
+    let server_entity = commands
+        .spawn((net_id, Networked, SpawnedBy(client_id), Owner(client_id)))
+        .insert((
+            model_data.model_info.clone(),
+            model_data.transform,
+            model_data.color128,
+            model_data.render_layer,
+        ))
+        .id();
+    entity_map.insert(server_entity, net_id);
     let endpoint = server.get_endpoint_mut().expect("no server endpoint");
-    endpoint
-        .send_lek_msg(
-            client_id,
-            ModelMsgClient::EntityMap(server_entity, client_entity),
-        )
-        .unwrap();
-    for client_id2 in endpoint.clients() {
-        if client_id2 == client_id {
+    for client_id2 in admitted.of(endpoint) {
+        if client_id2 == client_id || !is_relevant(interest.as_deref(), client_id2, net_id) {
             continue;
         }
         endpoint
             .send_lek_msg(
                 client_id2.clone(),
                 ModelMsgClient::ModelAdded(
-                    server_entity,
+                    net_id,
                     model_data.clone(),
                 ),
             )
             .unwrap()
     }
     system_state.apply(world);
-}
-
-fn new_client_connected(mut connected: EventReader<ConnectionEvent>, mut server: ResMut<Server>) {
-    let endpoint = server.endpoint_mut();
-    for client in connected.iter() {
-        let client_id: ClientId = client.id.clone();
-        for client_id2 in endpoint.clients() {
-            if client_id2 == client_id {
-                continue;
-            }
-            endpoint
-                .send_lek_msg(
-                    client_id2,
-                    ModelMsgClient::GetAllModelData(client_id.clone()),
-                )
-                .unwrap();
-        }
-    }
+    apply_pending_change(world, net_id);
+    true
 }
@@ -0,0 +1,129 @@
+use crate::networking::interpolation::TransformBuffer;
+use crate::networking::ownership_server::OwnershipMsgServer;
+use crate::networking::{IgnoreModelAdd, IgnorePlayerAdd};
+use bevy_app::App;
+use bevy_ecs::prelude::{
+    Added, Commands, Component, Entity, EventReader, Query, Res, ResMut, Without, World,
+};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::shared::channel::ChannelType;
+use leknet::{ClientMessage, EntityMap, LekClient, NetId, Networked, TypeName};
+use serde::{Deserialize, Serialize};
+
+/// this client has authority over the entity, only entities with it send their changes
+#[derive(Clone, Copy, Component, Debug)]
+pub struct Authority;
+
+/// asks the server for authority over a networked entity, e.g. because the user grabbed it
+pub struct RequestAuthority(pub Entity);
+
+/// gives up authority over a networked entity
+pub struct ReleaseAuthority(pub Entity);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum OwnershipMsgClient {
+    Granted(NetId),
+    Revoked(NetId),
+}
+
+impl TypeName for OwnershipMsgClient {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::OwnershipMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for OwnershipMsgClient {
+    fn client(self, world: &mut World) {
+        let net_id = match self {
+            OwnershipMsgClient::Granted(net_id) => net_id,
+            OwnershipMsgClient::Revoked(net_id) => net_id,
+        };
+        let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+            None => return,
+            Some(entity) => *entity,
+        };
+        let mut world_entity = match world.get_entity_mut(entity) {
+            None => return,
+            Some(world_entity) => world_entity,
+        };
+        match self {
+            OwnershipMsgClient::Granted(_) => {
+                // our own changes drive the transform from now on, stale remote states would pull it back
+                world_entity.insert(Authority);
+                world_entity.remove::<TransformBuffer>();
+            }
+            OwnershipMsgClient::Revoked(_) => {
+                world_entity.remove::<Authority>();
+            }
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            OwnershipMsgClient::Granted(_) => ChannelType::OrderedReliable,
+            OwnershipMsgClient::Revoked(_) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.add_event::<RequestAuthority>();
+        app.add_event::<ReleaseAuthority>();
+        app.add_system(claim_local_authority);
+        app.add_system(request_authority);
+        app.add_system(release_authority);
+    }
+}
+
+/// whoever spawns a networked entity starts out owning it
+fn claim_local_authority(
+    query: Query<Entity, (Added<Networked>, Without<IgnoreModelAdd>, Without<IgnorePlayerAdd>)>,
+    mut commands: Commands,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).insert(Authority);
+    }
+}
+
+fn request_authority(
+    mut requests: EventReader<RequestAuthority>,
+    mut client: ResMut<Client>,
+    entity_map: Res<EntityMap>,
+) {
+    if let Some(connection) = client.get_connection_mut() {
+        for request in requests.iter() {
+            if let Some(net_id) = entity_map.get_by_left(&request.0) {
+                connection
+                    .send_lek_msg(OwnershipMsgServer::Request(*net_id))
+                    .unwrap();
+            }
+        }
+    }
+}
+
+fn release_authority(
+    mut releases: EventReader<ReleaseAuthority>,
+    mut client: ResMut<Client>,
+    entity_map: Res<EntityMap>,
+    mut commands: Commands,
+) {
+    for release in releases.iter() {
+        let net_id = match entity_map.get_by_left(&release.0) {
+            None => continue,
+            Some(net_id) => *net_id,
+        };
+        if let Some(mut entity_commands) = commands.get_entity(release.0) {
+            entity_commands.remove::<Authority>();
+        }
+        if let Some(connection) = client.get_connection_mut() {
+            connection
+                .send_lek_msg(OwnershipMsgServer::Release(net_id))
+                .unwrap();
+        }
+    }
+}
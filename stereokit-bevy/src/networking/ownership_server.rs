@@ -0,0 +1,135 @@
+use crate::networking::permissions::{is_permitted, Permission};
+use crate::networking::prediction::LastAppliedSeq;
+use crate::networking::ownership_client::OwnershipMsgClient;
+use crate::networking::Player;
+use bevy_app::App;
+use bevy_ecs::prelude::{Commands, Component, Entity, EventReader, Query, ResMut, World};
+use bevy_quinnet::server::{ConnectionLostEvent, Server};
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use leknet::{EntityMap, LekServer, NetId, ServerMessage, TypeName};
+use serde::{Deserialize, Serialize};
+
+/// the client with authority over a networked entity, only present on the server
+#[derive(Clone, Copy, Component, Debug, PartialEq, Eq)]
+pub struct Owner(pub ClientId);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum OwnershipMsgServer {
+    Request(NetId),
+    Release(NetId),
+}
+
+impl TypeName for OwnershipMsgServer {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::OwnershipMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for OwnershipMsgServer {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        match self {
+            OwnershipMsgServer::Request(net_id) => request_msg(world, client_id, net_id),
+            OwnershipMsgServer::Release(net_id) => release_msg(world, client_id, net_id),
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            OwnershipMsgServer::Request(_) => ChannelType::OrderedReliable,
+            OwnershipMsgServer::Release(_) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.add_system(owner_disconnected);
+    }
+}
+
+/// whether updates from `client_id` about `net_id` should be accepted
+pub fn has_authority(world: &World, client_id: ClientId, net_id: NetId) -> bool {
+    world
+        .resource::<EntityMap>()
+        .get_by_right(&net_id)
+        .and_then(|entity| world.get::<Owner>(*entity))
+        .map_or(false, |owner| owner.0 == client_id)
+}
+
+/// makes `client_id` the owner of `net_id`, returning the previous owner if the request was granted
+pub(crate) fn transfer_ownership(
+    world: &mut World,
+    client_id: ClientId,
+    net_id: NetId,
+) -> Option<Option<Owner>> {
+    let entity = *world.resource::<EntityMap>().get_by_right(&net_id)?;
+    // players always belong to their own client
+    if world.get::<Player>(entity).is_some() {
+        return None;
+    }
+    if !is_permitted(world, client_id, Permission::RequestAuthority) {
+        return None;
+    }
+    let previous_owner = world.get::<Owner>(entity).copied();
+    if previous_owner == Some(Owner(client_id)) {
+        return None;
+    }
+    // the new owner numbers its changes from its own counter
+    world
+        .get_entity_mut(entity)?
+        .insert(Owner(client_id))
+        .remove::<LastAppliedSeq>();
+    Some(previous_owner)
+}
+
+fn request_msg(world: &mut World, client_id: ClientId, net_id: NetId) {
+    let previous_owner = match transfer_ownership(world, client_id, net_id) {
+        None => return,
+        Some(previous_owner) => previous_owner,
+    };
+    let mut server = world.resource_mut::<Server>();
+    let endpoint = server.endpoint_mut();
+    if let Some(previous_owner) = previous_owner {
+        if endpoint.clients().contains(&previous_owner.0) {
+            endpoint
+                .send_lek_msg(previous_owner.0, OwnershipMsgClient::Revoked(net_id))
+                .unwrap();
+        }
+    }
+    endpoint
+        .send_lek_msg(client_id, OwnershipMsgClient::Granted(net_id))
+        .unwrap();
+}
+
+fn release_msg(world: &mut World, client_id: ClientId, net_id: NetId) {
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    if world.get::<Player>(entity).is_some() {
+        return;
+    }
+    if world.get::<Owner>(entity) == Some(&Owner(client_id)) {
+        world.entity_mut(entity).remove::<Owner>();
+    }
+}
+
+/// whatever a client owned stays in the world, but nobody has authority over it until requested
+fn owner_disconnected(
+    mut disconnected: EventReader<ConnectionLostEvent>,
+    query: Query<(Entity, &Owner)>,
+    mut commands: Commands,
+) {
+    for client in disconnected.iter() {
+        for (entity, owner) in query.iter() {
+            if owner.0 == client.id {
+                commands.entity(entity).remove::<Owner>();
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::networking::id_server::can_claim;
+use crate::networking::interpolation::ServerStamp;
+use crate::networking::model_client::model_changed_msg;
+use crate::networking::player_client::player_changed_msg;
+use crate::networking::{model_server, player_server, ModelData2};
+use bevy_ecs::prelude::{Res, ResMut, Resource, World};
+use bevy_quinnet::shared::ClientId;
+use bevy_transform::prelude::Transform;
+use leknet::{EntityMap, NetId};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// how long updates for entities the client hasn't mapped yet are kept around
+#[derive(Resource, Clone, Debug)]
+pub struct PendingUpdateSettings {
+    pub timeout: Duration,
+}
+
+impl Default for PendingUpdateSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// unreliable updates that overtook the reliable message mapping their entity, only the latest is kept
+#[derive(Resource, Default)]
+pub(crate) struct PendingUpdates {
+    models: HashMap<NetId, (Instant, ModelData2, ServerStamp)>,
+    players: HashMap<NetId, (Instant, Transform, ServerStamp)>,
+}
+
+impl PendingUpdates {
+    pub(crate) fn defer_model(&mut self, net_id: NetId, model_data: ModelData2, stamp: ServerStamp) {
+        self.models.insert(net_id, (Instant::now(), model_data, stamp));
+    }
+
+    pub(crate) fn defer_player(&mut self, net_id: NetId, transform: Transform, stamp: ServerStamp) {
+        self.players.insert(net_id, (Instant::now(), transform, stamp));
+    }
+}
+
+/// a client's change to an entity the server hasn't spawned yet
+#[derive(Clone, Debug)]
+pub(crate) enum PendingChange {
+    Model(ModelData2, u32),
+    Player(Transform),
+}
+
+/// changes that overtook the reliable message spawning their entity on the server, only the latest is kept
+#[derive(Resource, Default)]
+pub(crate) struct PendingChanges(HashMap<NetId, (Instant, ClientId, PendingChange)>);
+
+impl PendingChanges {
+    pub(crate) fn take(&mut self, net_id: NetId) -> Option<(ClientId, PendingChange)> {
+        self.0
+            .remove(&net_id)
+            .map(|(_, client_id, change)| (client_id, change))
+    }
+
+    pub(crate) fn expire(&mut self, timeout: Duration) {
+        self.0.retain(|net_id, (since, _, _)| {
+            let keep = since.elapsed() < timeout;
+            if !keep {
+                println!("dropping change for {:?}, it was never spawned", net_id);
+            }
+            keep
+        });
+    }
+}
+
+/// keeps `change` for later if `net_id` is one the sender may still spawn, returning whether it was kept
+pub(crate) fn defer_change(
+    world: &mut World,
+    client_id: ClientId,
+    net_id: NetId,
+    change: PendingChange,
+) -> bool {
+    if !can_claim(world, client_id, net_id) {
+        return false;
+    }
+    world
+        .resource_mut::<PendingChanges>()
+        .0
+        .insert(net_id, (Instant::now(), client_id, change));
+    true
+}
+
+/// applies the change that was waiting for `net_id` to be spawned
+pub(crate) fn apply_pending_change(world: &mut World, net_id: NetId) {
+    let (client_id, change) = match world.resource_mut::<PendingChanges>().take(net_id) {
+        None => return,
+        Some(pending) => pending,
+    };
+    match change {
+        PendingChange::Model(model_data, seq) => {
+            if model_server::accept_seq(world, client_id, net_id, seq) {
+                model_server::model_changed_msg(world, client_id, net_id, model_data);
+                model_server::send_ack(world, client_id, net_id, seq);
+            }
+        }
+        PendingChange::Player(transform) => {
+            player_server::player_changed_msg(world, client_id, net_id, transform);
+        }
+    }
+}
+
+pub(crate) fn expire_pending_changes(
+    mut pending: ResMut<PendingChanges>,
+    settings: Option<Res<PendingUpdateSettings>>,
+) {
+    let timeout = settings.map_or(Duration::from_secs(5), |settings| settings.timeout);
+    pending.expire(timeout);
+}
+
+/// applies pending updates whose entity got mapped and drops the ones that waited too long
+pub(crate) fn flush_pending_updates(world: &mut World) {
+    let timeout = world
+        .get_resource::<PendingUpdateSettings>()
+        .map_or(Duration::from_secs(5), |settings| settings.timeout);
+    let entity_map = world.resource::<EntityMap>();
+    let pending = world.resource::<PendingUpdates>();
+    if pending.models.is_empty() && pending.players.is_empty() {
+        return;
+    }
+    let ready_models: Vec<NetId> = pending
+        .models
+        .keys()
+        .filter(|net_id| entity_map.contains_right(net_id))
+        .copied()
+        .collect();
+    let ready_players: Vec<NetId> = pending
+        .players
+        .keys()
+        .filter(|net_id| entity_map.contains_right(net_id))
+        .copied()
+        .collect();
+
+    let mut pending = world.resource_mut::<PendingUpdates>();
+    let models: Vec<(NetId, ModelData2, ServerStamp)> = ready_models
+        .into_iter()
+        .filter_map(|net_id| {
+            pending
+                .models
+                .remove(&net_id)
+                .map(|(_, model_data, stamp)| (net_id, model_data, stamp))
+        })
+        .collect();
+    let players: Vec<(NetId, Transform, ServerStamp)> = ready_players
+        .into_iter()
+        .filter_map(|net_id| {
+            pending
+                .players
+                .remove(&net_id)
+                .map(|(_, transform, stamp)| (net_id, transform, stamp))
+        })
+        .collect();
+    pending.models.retain(|net_id, (since, _, _)| {
+        let keep = since.elapsed() < timeout;
+        if !keep {
+            println!("dropping update for {:?}, it was never mapped", net_id);
+        }
+        keep
+    });
+    pending.players.retain(|net_id, (since, _, _)| {
+        let keep = since.elapsed() < timeout;
+        if !keep {
+            println!("dropping update for {:?}, it was never mapped", net_id);
+        }
+        keep
+    });
+    for (net_id, model_data, stamp) in models {
+        model_changed_msg(world, net_id, model_data, stamp);
+    }
+    for (net_id, transform, stamp) in players {
+        player_changed_msg(world, net_id, transform, stamp);
+    }
+}
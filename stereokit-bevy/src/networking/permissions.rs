@@ -0,0 +1,60 @@
+use bevy_ecs::prelude::{Resource, World};
+use bevy_quinnet::shared::ClientId;
+use leknet::{ClientRoles, Role};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// spawn models with `ModelMsgServer::ModelAdded`
+    SpawnModels,
+    /// take authority over entities other clients spawned
+    RequestAuthority,
+    /// despawn entities the client doesn't have authority over
+    DespawnAny,
+    /// send voice
+    Voice,
+}
+
+/// which permissions each role has, consulted by the server's message handlers
+#[derive(Resource, Clone, Debug)]
+pub struct PermissionTable(pub HashMap<Role, HashSet<Permission>>);
+
+impl Default for PermissionTable {
+    fn default() -> Self {
+        use Permission::*;
+        let guest = HashSet::from([RequestAuthority, Voice]);
+        let member = HashSet::from([SpawnModels, RequestAuthority, Voice]);
+        let moderator = HashSet::from([SpawnModels, RequestAuthority, Voice, DespawnAny]);
+        let host = HashSet::from([SpawnModels, RequestAuthority, Voice, DespawnAny]);
+        Self(HashMap::from([
+            (Role::Spectator, HashSet::new()),
+            (Role::Guest, guest),
+            (Role::Member, member),
+            (Role::Moderator, moderator),
+            (Role::Host, host),
+        ]))
+    }
+}
+
+impl PermissionTable {
+    pub fn allows(&self, role: Role, permission: Permission) -> bool {
+        self.0
+            .get(&role)
+            .map_or(false, |permissions| permissions.contains(&permission))
+    }
+}
+
+/// true if `client_id`'s role has `permission`
+pub fn is_permitted(world: &World, client_id: ClientId, permission: Permission) -> bool {
+    let role = match world.get_resource::<ClientRoles>() {
+        None => return true,
+        Some(roles) => roles.get(client_id),
+    };
+    let permitted = world
+        .get_resource::<PermissionTable>()
+        .map_or(true, |table| table.allows(role, permission));
+    if !permitted {
+        println!("client {} ({}) isn't allowed {:?}", client_id, role, permission);
+    }
+    permitted
+}
@@ -0,0 +1,177 @@
+use crate::networking::hierarchy_client::PendingParents;
+use crate::networking::replication::ReplicationRegistry;
+use crate::networking::ModelData;
+use crate::ModelInfo;
+use bevy_app::AppExit;
+use bevy_ecs::event::{Events, ManualEventReader};
+use bevy_ecs::prelude::{Entity, Local, Resource, With, World};
+use bevy_hierarchy::Parent;
+use bevy_transform::prelude::Transform;
+use leknet::{EntityMap, NetId, NetIdAllocator, Networked};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use stereokit::{Color128, RenderLayer};
+
+/// bump this whenever `SavedEntity` changes shape, older saves are then ignored instead of misread
+pub const WORLD_SAVE_VERSION: u32 = 1;
+
+/// saves every networked model to `path` periodically and on `AppExit`, and restores it on startup
+#[derive(Resource, Clone, Debug)]
+pub struct WorldPersistence {
+    pub path: PathBuf,
+    pub interval: Duration,
+}
+
+impl WorldPersistence {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntity {
+    net_id: NetId,
+    model_data: ModelData,
+    parent: Option<NetId>,
+    components: Vec<(String, Vec<u8>)>,
+}
+
+pub fn save_world(world: &mut World) -> io::Result<()> {
+    let path = match world.get_resource::<WorldPersistence>() {
+        None => return Ok(()),
+        Some(persistence) => persistence.path.clone(),
+    };
+    let models: Vec<(Entity, NetId, ModelData, Option<Entity>)> = world
+        .query_filtered::<(Entity, &NetId, &ModelInfo, &Transform, &Color128, &RenderLayer, Option<&Parent>), With<Networked>>()
+        .iter(world)
+        .map(|(entity, net_id, model_info, transform, color128, render_layer, parent)| {
+            (
+                entity,
+                *net_id,
+                ModelData {
+                    model_info: model_info.clone(),
+                    transform: *transform,
+                    color128: *color128,
+                    render_layer: *render_layer,
+                },
+                parent.map(|parent| parent.get()),
+            )
+        })
+        .collect();
+    let entity_map = world.resource::<EntityMap>();
+    let registry = world.resource::<ReplicationRegistry>();
+    let saved_entities: Vec<SavedEntity> = models
+        .into_iter()
+        .map(|(entity, net_id, model_data, parent)| SavedEntity {
+            net_id,
+            model_data,
+            parent: parent.and_then(|parent| entity_map.get_by_left(&parent).copied()),
+            components: registry
+                .iter()
+                .filter_map(|(name, replicated)| {
+                    (replicated.snapshot)(world, entity).map(|bytes| (name.clone(), bytes))
+                })
+                .collect(),
+        })
+        .collect();
+
+    let bytes = bincode::serialize(&(WORLD_SAVE_VERSION, saved_entities))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    // write next to the old save first so a crash mid write can't destroy it
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, bytes)?;
+    fs::rename(temp_path, path)
+}
+
+pub(crate) fn load_world(world: &mut World) {
+    let path = match world.get_resource::<WorldPersistence>() {
+        None => return,
+        Some(persistence) => persistence.path.clone(),
+    };
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(_) => return,
+    };
+    match bincode::deserialize::<u32>(&bytes) {
+        Ok(WORLD_SAVE_VERSION) => {}
+        Ok(version) => {
+            println!("ignoring {:?}, it was saved with version {} but {} is expected", path, version, WORLD_SAVE_VERSION);
+            return;
+        }
+        Err(e) => {
+            println!("couldn't read {:?}: {}", path, e);
+            return;
+        }
+    }
+    let saved_entities = match bincode::deserialize::<(u32, Vec<SavedEntity>)>(&bytes) {
+        Ok((_, saved_entities)) => saved_entities,
+        Err(e) => {
+            println!("couldn't read {:?}: {}", path, e);
+            return;
+        }
+    };
+    for saved_entity in saved_entities {
+        let model_data = saved_entity.model_data;
+        let entity = world
+            .spawn((saved_entity.net_id, Networked))
+            .insert((
+                model_data.model_info,
+                model_data.transform,
+                model_data.color128,
+                model_data.render_layer,
+            ))
+            .id();
+        world
+            .resource_mut::<EntityMap>()
+            .insert(entity, saved_entity.net_id);
+        world
+            .resource_mut::<NetIdAllocator>()
+            .reserve(saved_entity.net_id);
+        for (name, bytes) in saved_entity.components {
+            if let Some(replicated) = world.resource::<ReplicationRegistry>().get(&name) {
+                (replicated.apply)(world, entity, &bytes);
+            }
+        }
+        if let Some(parent) = saved_entity.parent {
+            world
+                .resource_mut::<PendingParents>()
+                .defer(saved_entity.net_id, Some(parent));
+        }
+    }
+}
+
+pub(crate) fn autosave_world(world: &mut World, mut last_save: Local<Option<Instant>>) {
+    let interval = match world.get_resource::<WorldPersistence>() {
+        None => return,
+        Some(persistence) => persistence.interval,
+    };
+    let last = *last_save.get_or_insert_with(Instant::now);
+    if last.elapsed() < interval {
+        return;
+    }
+    *last_save = Some(Instant::now());
+    if let Err(e) = save_world(world) {
+        println!("couldn't save the world: {}", e);
+    }
+}
+
+pub(crate) fn save_world_on_exit(
+    world: &mut World,
+    mut app_exit: Local<ManualEventReader<AppExit>>,
+) {
+    let exiting = app_exit
+        .iter(world.resource::<Events<AppExit>>())
+        .last()
+        .is_some();
+    if exiting {
+        if let Err(e) = save_world(world) {
+            println!("couldn't save the world: {}", e);
+        }
+    }
+}
@@ -1,27 +1,31 @@
 use bevy_app::App;
-use bevy_ecs::prelude::{Added, Changed, Commands, Entity, NonSend, Query, Res, ResMut, With, Without, World, Component};
+use bevy_ecs::prelude::{Changed, Commands, Entity, NonSend, Query, Res, ResMut, With, Without, World, Component};
 use bevy_ecs::system::SystemState;
 use bevy_quinnet::client::Client;
 use bevy_quinnet::shared::channel::ChannelType;
 use bevy_quinnet::shared::channel::ChannelType::{OrderedReliable, Unreliable};
-use bevy_quinnet::shared::ClientId;
 use bevy_transform::prelude::Transform;
 use bevy_transform::{TransformBundle, TransformPlugin};
-use leknet::{ClientEntity, ClientMessage, EntityMap, LekClient, Networked, ServerEntity, TypeName};
-use crate::networking::{IgnorePlayerAdd, IgnorePlayerChanged, ModelData, ModelData2, Player};
+use leknet::{ClientMessage, EntityMap, LekClient, NetId, Networked, RequestedRole, Role, TypeName};
+use crate::networking::{ClientMode, IgnorePlayerAdd, IgnorePlayerChanged, ModelData, ModelData2, Player};
 use serde::{Serialize, Deserialize};
 use stereokit::{Sk, SkDraw, StereoKitMultiThread};
 use crate::networking::player_server::PlayerMsgServer;
+use crate::networking::ownership_client::Authority;
+use crate::networking::pending::PendingUpdates;
+use crate::networking::quantize::{from_wire, QuantizationSettings, TransformEncoding, WireTransform};
+use crate::networking::id_client::NetIdPool;
+use crate::networking::interpolation::{receive_transform, ServerStamp};
 
 #[derive(Clone, Debug, Serialize, Deserialize, Component)]
 pub struct LocalPlayer;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum PlayerMsgClient {
-    PlayerAdded(ServerEntity, Transform),
-    PlayerChanged(ServerEntity, Transform),
-    EntityMap(ServerEntity, ClientEntity),
-    GetAllPlayers(ClientId),
+    PlayerAdded(NetId, Transform),
+    PlayerChanged(NetId, WireTransform, ServerStamp),
+    /// the server didn't spawn our player, it's sent again with another id
+    PlayerRejected(NetId),
 }
 
 impl TypeName for PlayerMsgClient {
@@ -33,19 +37,20 @@ impl TypeName for PlayerMsgClient {
 impl ClientMessage for PlayerMsgClient {
     fn client(self, world: &mut World) {
         match self {
-            PlayerMsgClient::PlayerAdded(server_entity, player_position) => {
-                player_added_msg(world, server_entity, player_position);
+            PlayerMsgClient::PlayerAdded(net_id, player_position) => {
+                player_added_msg(world, net_id, player_position);
             }
-            PlayerMsgClient::PlayerChanged(server_entity, player_position) => {
-                player_changed_msg(world, server_entity, player_position);
+            PlayerMsgClient::PlayerChanged(net_id, player_position, stamp) => {
+                let player_position = from_wire(world, &player_position);
+                player_changed_msg(world, net_id, player_position, stamp);
             }
-            PlayerMsgClient::EntityMap(server_entity, client_entity) => {
-                let mut system_state: SystemState<ResMut<EntityMap>> = SystemState::new(world);
-                let mut entity_map: ResMut<EntityMap> = system_state.get_mut(world);
-                entity_map.0.insert(client_entity, server_entity);
-            }
-            PlayerMsgClient::GetAllPlayers(client_id) => {
-                get_all_players_msg(world, client_id);
+            PlayerMsgClient::PlayerRejected(net_id) => {
+                let entity = world.resource_mut::<EntityMap>().remove_by_right(&net_id);
+                if let Some((entity, _)) = entity {
+                    if let Some(mut world_entity) = world.get_entity_mut(entity) {
+                        world_entity.remove::<NetId>();
+                    }
+                }
             }
         }
     }
@@ -57,101 +62,99 @@ impl ClientMessage for PlayerMsgClient {
     fn channel_type(&self) -> ChannelType {
         match self {
             PlayerMsgClient::PlayerAdded(_, _) => OrderedReliable,
-            PlayerMsgClient::PlayerChanged(_, _) => Unreliable,
-            PlayerMsgClient::EntityMap(_, _) => OrderedReliable,
-            PlayerMsgClient::GetAllPlayers(_) => OrderedReliable,
+            PlayerMsgClient::PlayerChanged(_, _, _) => Unreliable,
+            PlayerMsgClient::PlayerRejected(_) => OrderedReliable,
         }
     }
 
     fn plugin(app: &mut App) {
         app.add_system(player_added);
         app.add_system(player_changed);
+        app.init_resource::<ClientMode>();
+        app.add_startup_system(request_spectator_role);
         app.add_startup_system(spawn_player);
         app.add_system(sync_player);
     }
 }
 
-fn spawn_player(sk: NonSend<SkDraw>, mut commands: Commands) {
+fn spawn_player(sk: NonSend<SkDraw>, mode: Res<ClientMode>, mut commands: Commands) {
+    if *mode == ClientMode::Spectator {
+        return;
+    }
     let transform = Transform::from_translation(sk.input_head().position).with_rotation(sk.input_head().orientation);
     commands.spawn((Player, Networked, LocalPlayer)).insert(TransformBundle::from(transform));
 }
-fn sync_player(mut query: Query<(&LocalPlayer, &Player, &Networked, &mut Transform)>, sk: Res<Sk>) {
+fn sync_player(mut query: Query<(&LocalPlayer, &Player, &Networked, &mut Transform)>, sk: Res<Sk>, mode: Res<ClientMode>) {
+    if *mode == ClientMode::Spectator {
+        return;
+    }
     for (_, _, _, mut transform) in query.iter_mut() {
         transform.translation = sk.input_head().position;
         transform.rotation = sk.input_head().orientation;
     }
 }
 
-fn get_all_players_msg(world: &mut World, client_id: ClientId) {
-    let mut system_state: SystemState<(
-        Query<
-            (Entity, &Player, &Transform),
-            (With<Networked>),
-        >,
-        ResMut<Client>,
-        Res<EntityMap>,
-    )> = SystemState::new(world);
-    let (query, mut client, entity_map) = system_state.get_mut(world);
-    let mut client: ResMut<Client> = client;
-    let entity_map: Res<EntityMap> = entity_map;
-    let mut players = vec![];
-    for (entity, _, transform) in query.iter() {
-        let server_entity = match entity_map.get_by_left(&ClientEntity(entity)) {
-            None => continue,
-            Some(server_entity) => server_entity.clone(),
-        };
-        players.push((
-            server_entity,
-            *transform,
-        ))
+/// spectators join with the least privileged role so the server treats them as receive only
+fn request_spectator_role(mode: Res<ClientMode>, mut requested_role: ResMut<RequestedRole>) {
+    if *mode == ClientMode::Spectator {
+        requested_role.0 = Role::Spectator;
     }
-    client
-        .connection_mut()
-        .send_lek_msg(PlayerMsgServer::AllPlayerData(client_id, players))
-        .unwrap();
 }
-fn player_changed_msg(world: &mut World, server_entity: ServerEntity, transform: Transform) {
+
+pub(crate) fn player_changed_msg(world: &mut World, net_id: NetId, transform: Transform, stamp: ServerStamp) {
     let mut client_entity = None;
     {
         let mut system_state: SystemState<ResMut<EntityMap>> = SystemState::new(world);
         let mut entity_map = system_state.get_mut(world);
-        client_entity = entity_map.get_by_right(&server_entity).map(|a| a.clone());
+        client_entity = entity_map.get_by_right(&net_id).map(|a| a.clone());
     }
     if let Some(client_entity) = client_entity {
-        let mut world_entity = world.entity_mut(client_entity.0);
-        *world_entity.get_mut().unwrap() = transform;
-        //world_entity.insert(IgnorePlayerChanged);
+        receive_transform(world, client_entity, transform, stamp);
+    } else {
+        world
+            .resource_mut::<PendingUpdates>()
+            .defer_player(net_id, transform, stamp);
     }
 }
-fn player_added_msg(world: &mut World, server_entity: ServerEntity, transform: Transform) {
+fn player_added_msg(world: &mut World, net_id: NetId, transform: Transform) {
+    if world.resource::<EntityMap>().contains_right(&net_id) {
+        return;
+    }
     let mut system_state: SystemState<(ResMut<EntityMap>, Commands)> =
         SystemState::new(world);
     let (entity_map, commands) = system_state.get_mut(world);
     let mut entity_map: ResMut<EntityMap> = entity_map;
     let mut commands: Commands = commands;
-    let client_entity = ClientEntity(
-        commands
-            .spawn((Player, Networked))
-            .insert(TransformBundle::from(transform))
-            .insert(IgnorePlayerAdd)
-            .id(),
-    );
-    entity_map.insert(client_entity, server_entity);
+    let client_entity = commands
+        .spawn((Player, Networked, net_id))
+        .insert(TransformBundle::from(transform))
+        .insert(IgnorePlayerAdd)
+        .id();
+    entity_map.insert(client_entity, net_id);
     system_state.apply(world);
 }
 
 fn player_added(
     query: Query<
         (Entity, &Transform, &Player),
-        (Added<Networked>, Without<IgnorePlayerAdd>),
+        (With<Networked>, Without<NetId>, Without<IgnorePlayerAdd>),
     >,
     mut client: ResMut<Client>,
+    mut pool: ResMut<NetIdPool>,
+    mut entity_map: ResMut<EntityMap>,
+    mut commands: Commands,
 ) {
     if let Some(connection) = client.get_connection_mut() {
         for (entity, transform, _) in query.iter() {
+            let net_id = match pool.next() {
+                None => return,
+                Some(net_id) => net_id,
+            };
+            entity_map.insert(entity, net_id);
+            commands.entity(entity).insert(net_id);
             connection
                 .send_lek_msg(PlayerMsgServer::PlayerAdded(
-                    ClientEntity(entity),
+                    net_id,
                     *transform,
                 ))
                 .unwrap()
@@ -165,19 +168,24 @@ fn player_changed(
         (
             Changed<Transform>,
             Without<IgnorePlayerChanged>,
+            With<Authority>,
             With<Networked>,
         ),
     >,
     mut client: ResMut<Client>,
     entity_map: Res<EntityMap>,
+    encoding: Option<Res<TransformEncoding>>,
+    quantization: Option<Res<QuantizationSettings>>,
 ) {
+    let encoding = encoding.map_or(TransformEncoding::default(), |encoding| *encoding);
+    let quantization = quantization.map_or(QuantizationSettings::default(), |quantization| *quantization);
     if let Some(connection) = client.get_connection_mut() {
         for (entity, transform, _) in query.iter() {
-            if let Some(server_entity) = entity_map.get_by_left(&ClientEntity(entity)) {
+            if let Some(net_id) = entity_map.get_by_left(&entity) {
                 connection
                     .send_lek_msg(PlayerMsgServer::PlayerChanged(
-                        *server_entity,
-                        *transform,
+                        *net_id,
+                        WireTransform::encode(transform, encoding, &quantization),
                     ))
                     .unwrap()
             }
@@ -1,20 +1,27 @@
 use bevy_app::App;
-use bevy_ecs::prelude::{Commands, EventReader, ResMut, World};
+use bevy_ecs::prelude::{Commands, Entity, EventReader, Query, Res, ResMut, With, World};
 use bevy_ecs::system::SystemState;
-use bevy_quinnet::server::{ConnectionEvent, Server};
+use bevy_quinnet::server::{ConnectionLostEvent, Server};
 use bevy_quinnet::shared::channel::ChannelType;
 use bevy_quinnet::shared::channel::ChannelType::{OrderedReliable, Unreliable};
 use bevy_quinnet::shared::ClientId;
 use bevy_transform::components::Transform;
-use leknet::{ClientEntity, LekServer, ServerEntity, ServerMessage, TypeName};
+use leknet::{AdmittedClients, EntityMap, LekServer, NetId, Networked, ServerMessage, TypeName};
 use serde::{Serialize, Deserialize};
+use crate::networking::id_server::{can_claim, GrantedIds};
+use crate::networking::interest::{is_relevant, ClientInterest};
+use crate::networking::interpolation::ServerStamp;
+use crate::networking::quantize::{from_wire, quantized, to_wire, WireTransform};
+use crate::networking::ownership_server::{has_authority, Owner};
+use crate::networking::pending::{apply_pending_change, defer_change, PendingChange};
+use crate::networking::validation::{send_correction, validate, Validation};
 use crate::networking::player_client::PlayerMsgClient;
+use crate::networking::{Player, SpawnedBy};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum PlayerMsgServer {
-    PlayerAdded(ClientEntity, Transform),
-    PlayerChanged(ServerEntity, Transform),
-    AllPlayerData(ClientId, Vec<(ServerEntity, Transform)>),
+    PlayerAdded(NetId, Transform),
+    PlayerChanged(NetId, WireTransform),
 }
 impl TypeName for PlayerMsgServer {
     fn get_type_name() -> String {
@@ -22,28 +29,33 @@ impl TypeName for PlayerMsgServer {
     }
 }
 impl ServerMessage for PlayerMsgServer {
-    fn server(self, world: &mut World, client_id: ClientId) {
-        match self {
-            PlayerMsgServer::PlayerAdded(client_entity, player_data) => {
-                player_added_msg(world, client_id, client_entity, player_data)
+    fn server(mut self, world: &mut World, client_id: ClientId) {
+        let validation = validate(world, client_id, &mut self);
+        let corrected = match &self {
+            PlayerMsgServer::PlayerChanged(net_id, _) if validation != Validation::Accept => Some(*net_id),
+            _ => None,
+        };
+        if validation == Validation::Reject {
+            if let Some(net_id) = corrected {
+                send_correction(world, client_id, net_id);
             }
-            PlayerMsgServer::PlayerChanged(server_entity, player_data) => {
-                player_changed_msg(world, client_id, server_entity, player_data)
+            return;
+        }
+        match self {
+            PlayerMsgServer::PlayerAdded(net_id, player_data) => {
+                player_added_msg(world, client_id, net_id, player_data)
             }
-            PlayerMsgServer::AllPlayerData(client_id, all_player_data) => {
-                let mut endpoint: SystemState<ResMut<Server>> = SystemState::new(world);
-                let mut endpoint = endpoint.get_mut(world);
-                let endpoint = endpoint.endpoint_mut();
-                for (entity, player_data) in all_player_data {
-                    endpoint
-                        .send_lek_msg(
-                            client_id.clone(),
-                            PlayerMsgClient::PlayerAdded(entity, player_data),
-                        )
-                        .unwrap();
+            PlayerMsgServer::PlayerChanged(net_id, player_data) => {
+                let player_data = from_wire(world, &player_data);
+                // the change overtook the player it belongs to, it's applied once the player is added
+                if !defer_change(world, client_id, net_id, PendingChange::Player(player_data)) {
+                    player_changed_msg(world, client_id, net_id, player_data)
                 }
             }
         }
+        if let Some(net_id) = corrected {
+            send_correction(world, client_id, net_id);
+        }
     }
 
     fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
@@ -54,80 +66,104 @@ impl ServerMessage for PlayerMsgServer {
         match self {
             PlayerMsgServer::PlayerAdded(_, _) => OrderedReliable,
             PlayerMsgServer::PlayerChanged(_, _) => Unreliable,
-            PlayerMsgServer::AllPlayerData(_, _) => OrderedReliable,
         }
     }
 
     fn plugin(app: &mut App) {
-        app.add_system(new_client_connected);
+        app.add_system(client_disconnected);
     }
 }
 
-fn player_changed_msg(world: &mut World, client_id: ClientId, server_entity: ServerEntity, player_data: Transform) {
-    let mut system_state: SystemState<ResMut<Server>> = SystemState::new(world);
-    let mut server = system_state.get_mut(world);
+pub(crate) fn player_changed_msg(world: &mut World, client_id: ClientId, net_id: NetId, player_data: Transform) {
+    if !has_authority(world, client_id, net_id) {
+        return;
+    }
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    // keep what the other clients will decode, so the server's state doesn't drift from theirs
+    let player_data = quantized(world, &player_data);
+    if let Some(mut world_entity) = world.get_entity_mut(entity) {
+        *world_entity.get_mut().unwrap() = player_data;
+    }
+    let stamp = ServerStamp::now(world);
+    let wire_transform = to_wire(world, &player_data);
+    let mut system_state: SystemState<(ResMut<Server>, Option<Res<ClientInterest>>, Res<AdmittedClients>)> =
+        SystemState::new(world);
+    let (mut server, interest, admitted) = system_state.get_mut(world);
     let endpoint = server.endpoint_mut();
-    for client_id2 in endpoint.clients() {
-        if client_id2 == client_id {
+    for client_id2 in admitted.of(endpoint) {
+        if client_id2 == client_id || !is_relevant(interest.as_deref(), client_id2, net_id) {
             continue;
         }
         endpoint
             .send_lek_msg(
                 client_id2.clone(),
                 PlayerMsgClient::PlayerChanged(
-                    server_entity,
-                    player_data.clone(),
+                    net_id,
+                    wire_transform,
+                    stamp,
                 ),
             )
             .unwrap();
     }
 }
 
-fn player_added_msg(world: &mut World, client_id: ClientId, client_entity: ClientEntity, player_data: Transform) {
-    let mut system_state: SystemState<(ResMut<Server>, Commands)> =
-        SystemState::new(world);
-    let (mut server, mut commands) = system_state.get_mut(world);
+fn player_added_msg(world: &mut World, client_id: ClientId, net_id: NetId, player_data: Transform) {
+    if !can_claim(world, client_id, net_id) {
+        world
+            .resource_mut::<Server>()
+            .endpoint_mut()
+            .send_lek_msg(client_id, PlayerMsgClient::PlayerRejected(net_id))
+            .unwrap();
+        return;
+    }
+    world.resource_mut::<GrantedIds>().claim(client_id, net_id);
+    let mut system_state: SystemState<(
+        ResMut<Server>,
+        ResMut<EntityMap>,
+        Option<Res<ClientInterest>>,
+        Res<AdmittedClients>,
+        Commands,
+    )> = SystemState::new(world);
+    let (mut server, mut entity_map, interest, admitted, mut commands) = system_state.get_mut(world);
     let mut server: ResMut<Server> = server;
     let mut commands: Commands = commands;
-    let server_entity = ServerEntity(commands.spawn_empty().id());
+    let server_entity = commands
+        .spawn((Player, net_id, Networked, SpawnedBy(client_id), Owner(client_id)))
+        .insert(player_data)
+        .id();
+    entity_map.insert(server_entity, net_id);
     let endpoint = server.get_endpoint_mut().expect("no server endpoint");
-    endpoint
-        .send_lek_msg(
-            client_id,
-            PlayerMsgClient::EntityMap(server_entity, client_entity),
-        )
-        .unwrap();
-    for client_id2 in endpoint.clients() {
-        if client_id2 == client_id {
+    for client_id2 in admitted.of(endpoint) {
+        if client_id2 == client_id || !is_relevant(interest.as_deref(), client_id2, net_id) {
             continue;
         }
         endpoint
             .send_lek_msg(
                 client_id2.clone(),
                 PlayerMsgClient::PlayerAdded(
-                    server_entity,
+                    net_id,
                     player_data.clone(),
                 ),
             )
             .unwrap()
     }
     system_state.apply(world);
+    apply_pending_change(world, net_id);
 }
 
-fn new_client_connected(mut connected: EventReader<ConnectionEvent>, mut server: ResMut<Server>) {
-    let endpoint = server.endpoint_mut();
-    for client in connected.iter() {
-        let client_id: ClientId = client.id.clone();
-        for client_id2 in endpoint.clients() {
-            if client_id2 == client_id {
-                continue;
+fn client_disconnected(
+    mut disconnected: EventReader<ConnectionLostEvent>,
+    query: Query<(Entity, &SpawnedBy), With<Player>>,
+    mut commands: Commands,
+) {
+    for client in disconnected.iter() {
+        for (entity, spawned_by) in query.iter() {
+            if spawned_by.0 == client.id {
+                commands.entity(entity).despawn();
             }
-            endpoint
-                .send_lek_msg(
-                    client_id2,
-                    PlayerMsgClient::GetAllPlayers(client_id.clone()),
-                )
-                .unwrap();
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,66 @@
+use bevy_ecs::prelude::Component;
+use bevy_transform::prelude::Transform;
+use std::collections::VecDeque;
+
+const MAX_UNACKED: usize = 64;
+
+/// whether `seq` was sent after `than`, sequence numbers wrap around
+pub(crate) fn is_newer(seq: u32, than: u32) -> bool {
+    (seq.wrapping_sub(than) as i32) > 0
+}
+
+/// the sequence number of the last change the server applied to this entity, older ones are dropped
+#[derive(Component, Clone, Copy, Debug)]
+pub(crate) struct LastAppliedSeq(pub(crate) u32);
+
+/// transforms this client applied locally and sent, but the server hasn't confirmed yet
+#[derive(Component, Default)]
+pub struct PredictionHistory {
+    next_seq: u32,
+    unacked: VecDeque<(u32, Transform)>,
+}
+
+impl PredictionHistory {
+    /// remembers `transform` as predicted and returns the sequence number to send it with
+    pub(crate) fn record(&mut self, transform: Transform) -> u32 {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.unacked.push_back((seq, transform));
+        while self.unacked.len() > MAX_UNACKED {
+            self.unacked.pop_front();
+        }
+        seq
+    }
+
+    /// takes the server's state after `seq` and, if it disagrees with what we predicted,
+    /// replays the later unconfirmed changes on top of it, returning where the entity should be now
+    pub(crate) fn reconcile(&mut self, seq: u32, server: &Transform, current: &Transform) -> Option<Transform> {
+        while self.unacked.front().map_or(false, |(unacked_seq, _)| is_newer(seq, *unacked_seq)) {
+            self.unacked.pop_front();
+        }
+        let predicted = match self.unacked.front() {
+            Some((unacked_seq, predicted)) if *unacked_seq == seq => *predicted,
+            _ => return None,
+        };
+        self.unacked.pop_front();
+        if approx_eq(&predicted, server) {
+            return None;
+        }
+        // scale is replayed as an offset like translation, a ratio would divide by zero on flattened models
+        let replay = |transform: &Transform| Transform {
+            translation: server.translation + (transform.translation - predicted.translation),
+            rotation: (server.rotation * predicted.rotation.inverse() * transform.rotation).normalize(),
+            scale: server.scale + (transform.scale - predicted.scale),
+        };
+        for (_, transform) in self.unacked.iter_mut() {
+            *transform = replay(transform);
+        }
+        Some(replay(current))
+    }
+}
+
+fn approx_eq(a: &Transform, b: &Transform) -> bool {
+    a.translation.abs_diff_eq(b.translation, 1e-4)
+        && a.rotation.abs_diff_eq(b.rotation, 1e-4)
+        && a.scale.abs_diff_eq(b.scale, 1e-4)
+}
@@ -0,0 +1,261 @@
+use crate::networking::ModelData2;
+use bevy_ecs::prelude::{Resource, World};
+use bevy_transform::prelude::Transform;
+use glam::{Quat, Vec3};
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use stereokit::{Color128, RenderLayer};
+
+/// how transforms are encoded when this peer sends them, receivers understand both
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransformEncoding {
+    #[default]
+    Raw,
+    /// quantized position, smallest-three rotation and scale only when it isn't one,
+    /// every peer needs the same `QuantizationSettings`
+    Compact,
+}
+
+/// the world volume compact positions are quantized in, positions outside it are clamped
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct QuantizationSettings {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Default for QuantizationSettings {
+    fn default() -> Self {
+        Self {
+            min: Vec3::splat(-32.0),
+            max: Vec3::splat(32.0),
+        }
+    }
+}
+
+impl QuantizationSettings {
+    /// the largest error a quantized position can have along each axis
+    pub fn precision(&self) -> Vec3 {
+        (self.max - self.min) / u16::MAX as f32 / 2.0
+    }
+}
+
+const ROTATION_BITS: u32 = 10;
+const ROTATION_MAX: u32 = (1 << ROTATION_BITS) - 1;
+const ROTATION_RANGE: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+const SCALE_ONE: u8 = 0;
+const SCALE_UNIFORM: u8 = 1;
+const SCALE_FULL: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CompactScale {
+    One,
+    Uniform(f32),
+    Full(Vec3),
+}
+
+/// a transform in 11 to 23 bytes instead of 40
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactTransform {
+    position: [u16; 3],
+    /// index of the dropped largest component, and the other three at `ROTATION_BITS` each
+    rotation: u32,
+    scale: CompactScale,
+}
+
+impl CompactTransform {
+    pub fn encode(transform: &Transform, settings: &QuantizationSettings) -> Self {
+        let normalized = ((transform.translation - settings.min) / (settings.max - settings.min))
+            .clamp(Vec3::ZERO, Vec3::ONE);
+        let position = (normalized * u16::MAX as f32).round();
+        let scale = transform.scale;
+        Self {
+            position: [position.x as u16, position.y as u16, position.z as u16],
+            rotation: encode_rotation(transform.rotation),
+            scale: if scale == Vec3::ONE {
+                CompactScale::One
+            } else if scale.x == scale.y && scale.y == scale.z {
+                CompactScale::Uniform(scale.x)
+            } else {
+                CompactScale::Full(scale)
+            },
+        }
+    }
+
+    pub fn decode(&self, settings: &QuantizationSettings) -> Transform {
+        let normalized = Vec3::new(
+            self.position[0] as f32,
+            self.position[1] as f32,
+            self.position[2] as f32,
+        ) / u16::MAX as f32;
+        Transform {
+            translation: settings.min + normalized * (settings.max - settings.min),
+            rotation: decode_rotation(self.rotation),
+            scale: match self.scale {
+                CompactScale::One => Vec3::ONE,
+                CompactScale::Uniform(scale) => Vec3::splat(scale),
+                CompactScale::Full(scale) => scale,
+            },
+        }
+    }
+}
+
+/// smallest three: the largest component is implied by the others since the quaternion is unit length
+fn encode_rotation(rotation: Quat) -> u32 {
+    let mut components = rotation.normalize().to_array();
+    let largest = (0..4)
+        .max_by(|a, b| components[*a].abs().total_cmp(&components[*b].abs()))
+        .unwrap();
+    // q and -q are the same rotation, so the dropped component can always be positive
+    if components[largest] < 0.0 {
+        components.iter_mut().for_each(|component| *component = -*component);
+    }
+    let mut packed = largest as u32;
+    for (index, component) in components.iter().enumerate() {
+        if index == largest {
+            continue;
+        }
+        let normalized = ((component / ROTATION_RANGE + 1.0) / 2.0).clamp(0.0, 1.0);
+        packed = (packed << ROTATION_BITS) | (normalized * ROTATION_MAX as f32).round() as u32;
+    }
+    packed
+}
+
+fn decode_rotation(packed: u32) -> Quat {
+    let largest = (packed >> (ROTATION_BITS * 3)) as usize & 0b11;
+    let mut components = [0.0; 4];
+    let mut shift = ROTATION_BITS * 3;
+    let mut sum = 0.0;
+    for (index, component) in components.iter_mut().enumerate() {
+        if index == largest {
+            continue;
+        }
+        shift -= ROTATION_BITS;
+        let normalized = ((packed >> shift) & ROTATION_MAX) as f32 / ROTATION_MAX as f32;
+        *component = (normalized * 2.0 - 1.0) * ROTATION_RANGE;
+        sum += *component * *component;
+    }
+    components[largest] = (1.0 - sum).max(0.0).sqrt();
+    Quat::from_array(components).normalize()
+}
+
+impl Serialize for CompactTransform {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // like `ModelDelta`, bincode writes no tuple length so only the scale that's needed costs anything
+        let mut tuple = serializer.serialize_tuple(4)?;
+        tuple.serialize_element(&self.position)?;
+        tuple.serialize_element(&self.rotation)?;
+        match self.scale {
+            CompactScale::One => tuple.serialize_element(&SCALE_ONE)?,
+            CompactScale::Uniform(scale) => {
+                tuple.serialize_element(&SCALE_UNIFORM)?;
+                tuple.serialize_element(&scale)?;
+            }
+            CompactScale::Full(scale) => {
+                tuple.serialize_element(&SCALE_FULL)?;
+                tuple.serialize_element(&scale)?;
+            }
+        }
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for CompactTransform {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CompactVisitor;
+
+        impl<'de> Visitor<'de> for CompactVisitor {
+            type Value = CompactTransform;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a quantized position, packed rotation and optional scale")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<CompactTransform, A::Error> {
+                let missing = || A::Error::custom("compact transform is missing a field");
+                let position = seq.next_element()?.ok_or_else(missing)?;
+                let rotation = seq.next_element()?.ok_or_else(missing)?;
+                let scale = match seq.next_element::<u8>()?.ok_or_else(missing)? {
+                    SCALE_ONE => CompactScale::One,
+                    SCALE_UNIFORM => CompactScale::Uniform(seq.next_element()?.ok_or_else(missing)?),
+                    SCALE_FULL => CompactScale::Full(seq.next_element()?.ok_or_else(missing)?),
+                    kind => return Err(A::Error::custom(format!("unknown scale kind {}", kind))),
+                };
+                Ok(CompactTransform {
+                    position,
+                    rotation,
+                    scale,
+                })
+            }
+        }
+
+        deserializer.deserialize_tuple(4, CompactVisitor)
+    }
+}
+
+/// a transform as it goes over the wire
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WireTransform {
+    Raw(Transform),
+    Compact(CompactTransform),
+}
+
+impl WireTransform {
+    pub fn encode(transform: &Transform, encoding: TransformEncoding, settings: &QuantizationSettings) -> Self {
+        match encoding {
+            TransformEncoding::Raw => WireTransform::Raw(*transform),
+            TransformEncoding::Compact => WireTransform::Compact(CompactTransform::encode(transform, settings)),
+        }
+    }
+
+    pub fn decode(&self, settings: &QuantizationSettings) -> Transform {
+        match self {
+            WireTransform::Raw(transform) => *transform,
+            WireTransform::Compact(compact) => compact.decode(settings),
+        }
+    }
+}
+
+/// a `ModelData2` with its transform encoded like `WireTransform`
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct WireModelData {
+    pub transform: WireTransform,
+    pub color128: Color128,
+    pub render_layer: RenderLayer,
+}
+
+/// `transform` after a trip over the wire with this peer's encoding
+pub(crate) fn quantized(world: &World, transform: &Transform) -> Transform {
+    from_wire(world, &to_wire(world, transform))
+}
+
+pub(crate) fn model_to_wire(world: &World, model_data: &ModelData2) -> WireModelData {
+    WireModelData {
+        transform: to_wire(world, &model_data.transform),
+        color128: model_data.color128,
+        render_layer: model_data.render_layer,
+    }
+}
+
+pub(crate) fn model_from_wire(world: &World, wire_model_data: &WireModelData) -> ModelData2 {
+    ModelData2 {
+        transform: from_wire(world, &wire_model_data.transform),
+        color128: wire_model_data.color128,
+        render_layer: wire_model_data.render_layer,
+    }
+}
+
+/// encodes `transform` the way this peer is configured to send transforms
+pub(crate) fn to_wire(world: &World, transform: &Transform) -> WireTransform {
+    WireTransform::encode(
+        transform,
+        world.get_resource::<TransformEncoding>().copied().unwrap_or_default(),
+        &world.get_resource::<QuantizationSettings>().copied().unwrap_or_default(),
+    )
+}
+
+pub(crate) fn from_wire(world: &World, wire_transform: &WireTransform) -> Transform {
+    wire_transform.decode(&world.get_resource::<QuantizationSettings>().copied().unwrap_or_default())
+}
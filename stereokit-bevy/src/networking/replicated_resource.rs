@@ -0,0 +1,143 @@
+use crate::networking::resource_client::ResourceMsgClient;
+use crate::networking::resource_server::ResourceMsgServer;
+use bevy_ecs::prelude::{EventReader, Res, ResMut, Resource, World};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::ClientId;
+use leknet::{AdmittedClients, ClientAdmitted, LekClient, LekServer};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::type_name;
+use std::collections::HashMap;
+
+/// ask the server to replace the replicated resource `R`, it's only applied if the server permits it
+pub struct ResourceChangeRequest<R>(pub R);
+
+/// decides whether a client may replace the server's `R`
+#[derive(Resource)]
+pub(crate) struct ResourcePermission<R: Resource>(
+    pub(crate) Box<dyn Fn(&World, ClientId, &R) -> bool + Send + Sync>,
+);
+
+#[derive(Clone, Copy)]
+pub(crate) struct ReplicatedResource {
+    pub(crate) apply: fn(&mut World, &[u8]),
+    pub(crate) request: fn(&mut World, ClientId, &[u8]),
+}
+
+/// type erased access to every replicated resource, keyed by type name
+#[derive(Resource, Default)]
+pub struct ResourceRegistry(HashMap<String, ReplicatedResource>);
+
+impl ResourceRegistry {
+    pub(crate) fn register<R: Resource + Serialize + DeserializeOwned>(&mut self) {
+        self.0.insert(
+            type_name::<R>().to_string(),
+            ReplicatedResource {
+                apply: apply_resource::<R>,
+                request: request_resource::<R>,
+            },
+        );
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<ReplicatedResource> {
+        self.0.get(name).copied()
+    }
+}
+
+fn apply_resource<R: Resource + DeserializeOwned>(world: &mut World, bytes: &[u8]) {
+    if let Ok(resource) = bincode::deserialize::<R>(bytes) {
+        world.insert_resource(resource);
+    }
+}
+
+fn request_resource<R: Resource + Serialize + DeserializeOwned>(
+    world: &mut World,
+    client_id: ClientId,
+    bytes: &[u8],
+) {
+    let resource = match bincode::deserialize::<R>(bytes) {
+        Ok(resource) => resource,
+        Err(_) => return,
+    };
+    let permitted = match world.get_resource::<ResourcePermission<R>>() {
+        None => false,
+        Some(permission) => (permission.0)(world, client_id, &resource),
+    };
+    if permitted {
+        // `resource_changed` pushes it to every client, the requester included
+        world.insert_resource(resource);
+        return;
+    }
+    let current = match world.get_resource::<R>() {
+        None => return,
+        Some(current) => bincode::serialize(current).unwrap(),
+    };
+    let mut server = world.resource_mut::<Server>();
+    server
+        .endpoint_mut()
+        .send_lek_msg(
+            client_id,
+            ResourceMsgClient::Changed(type_name::<R>().to_string(), current),
+        )
+        .unwrap();
+}
+
+pub(crate) fn resource_changed<R: Resource + Serialize>(
+    resource: Option<Res<R>>,
+    mut server: ResMut<Server>,
+    admitted: Res<AdmittedClients>,
+) {
+    let resource = match resource {
+        Some(resource) if resource.is_changed() => resource,
+        _ => return,
+    };
+    if let Some(endpoint) = server.get_endpoint_mut() {
+        let bytes = bincode::serialize(&*resource).unwrap();
+        for client_id in admitted.of(endpoint) {
+            endpoint
+                .send_lek_msg(
+                    client_id,
+                    ResourceMsgClient::Changed(type_name::<R>().to_string(), bytes.clone()),
+                )
+                .unwrap();
+        }
+    }
+}
+
+pub(crate) fn resource_snapshot<R: Resource + Serialize>(
+    mut admitted: EventReader<ClientAdmitted>,
+    resource: Option<Res<R>>,
+    mut server: ResMut<Server>,
+) {
+    let endpoint = server.endpoint_mut();
+    for admitted in admitted.iter() {
+        if let Some(resource) = resource.as_ref() {
+            endpoint
+                .send_lek_msg(
+                    admitted.client_id,
+                    ResourceMsgClient::Changed(
+                        type_name::<R>().to_string(),
+                        bincode::serialize(&**resource).unwrap(),
+                    ),
+                )
+                .unwrap();
+        }
+    }
+}
+
+pub(crate) fn request_resource_change<R: Resource + Serialize>(
+    mut requests: EventReader<ResourceChangeRequest<R>>,
+    mut client: ResMut<Client>,
+) {
+    if let Some(connection) = client.get_connection_mut() {
+        for request in requests.iter() {
+            connection
+                .send_lek_msg(ResourceMsgServer::RequestChange(
+                    type_name::<R>().to_string(),
+                    bincode::serialize(&request.0).unwrap(),
+                ))
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::networking::component_client::ComponentMsgClient;
+use crate::networking::component_server::ComponentMsgServer;
+use crate::networking::interest::{is_relevant, ClientInterest};
+use crate::networking::ownership_client::Authority;
+use crate::networking::replicated_resource::{
+    request_resource_change, resource_changed, resource_snapshot, ResourceChangeRequest,
+    ResourcePermission, ResourceRegistry,
+};
+use bevy_app::CoreSet;
+use bevy_app::App;
+use bevy_ecs::prelude::{
+    Changed, Component, Entity, IntoSystemConfig, Query, RemovedComponents, Res, ResMut,
+    Resource, With, World,
+};
+use bevy_quinnet::client::Client;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::ClientId;
+use bevy_reflect::{GetTypeRegistration, Reflect};
+use leknet::{AdmittedClients, EntityMap, LekClient, LekServer, NetId, Networked};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::type_name;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+pub trait ReplicateAppExt {
+    /// keeps `C` in sync between every peer for all `Networked` entities
+    fn replicate<C>(&mut self) -> &mut Self
+    where
+        C: Component + Reflect + GetTypeRegistration + Serialize + DeserializeOwned;
+
+    /// pushes the server's `R` to every client on join and whenever it changes
+    fn replicate_resource<R>(&mut self) -> &mut Self
+    where
+        R: Resource + Serialize + DeserializeOwned;
+
+    /// lets clients replace the server's `R` through `ResourceChangeRequest<R>` when `permission` allows it
+    fn permit_resource_change<R>(
+        &mut self,
+        permission: impl Fn(&World, ClientId, &R) -> bool + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        R: Resource;
+}
+
+fn assert_networked<T>(app: &App) -> (bool, bool) {
+    let is_client = leknet::is_client(app);
+    let is_server = leknet::is_server(app);
+    assert!(
+        is_client || is_server,
+        "add StereoKitBevyClientPlugins or StereoKitBevyServerPlugins before replicating {}",
+        type_name::<T>()
+    );
+    (is_client, is_server)
+}
+
+impl ReplicateAppExt for App {
+    fn replicate<C>(&mut self) -> &mut Self
+    where
+        C: Component + Reflect + GetTypeRegistration + Serialize + DeserializeOwned,
+    {
+        let (is_client, is_server) = assert_networked::<C>(self);
+        self.register_type::<C>();
+        self.world
+            .resource_mut::<ReplicationRegistry>()
+            .register::<C>();
+        self.insert_resource(ReceivedComponents::<C>::default());
+        if is_client {
+            self.add_system(component_changed::<C>);
+            self.add_system(component_removed::<C>);
+        }
+        if is_server {
+            self.add_system(server_component_changed::<C>);
+            self.add_system(server_component_removed::<C>);
+        }
+        if let Some(server_app) = leknet::listen_server_mut(self) {
+            server_app.replicate::<C>();
+        }
+        self
+    }
+
+    fn replicate_resource<R>(&mut self) -> &mut Self
+    where
+        R: Resource + Serialize + DeserializeOwned,
+    {
+        let (is_client, is_server) = assert_networked::<R>(self);
+        self.world
+            .resource_mut::<ResourceRegistry>()
+            .register::<R>();
+        if is_client {
+            self.add_event::<ResourceChangeRequest<R>>();
+            self.add_system(request_resource_change::<R>);
+        }
+        if is_server {
+            self.add_system(resource_changed::<R>.in_base_set(CoreSet::PostUpdate));
+            self.add_system(resource_snapshot::<R>);
+        }
+        if let Some(server_app) = leknet::listen_server_mut(self) {
+            server_app.replicate_resource::<R>();
+        }
+        self
+    }
+
+    fn permit_resource_change<R>(
+        &mut self,
+        permission: impl Fn(&World, ClientId, &R) -> bool + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        R: Resource,
+    {
+        match leknet::listen_server_mut(self) {
+            Some(server_app) => {
+                server_app.permit_resource_change(permission);
+                self
+            }
+            None => self.insert_resource(ResourcePermission::<R>(Box::new(permission))),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct ReplicatedComponent {
+    pub(crate) apply: fn(&mut World, Entity, &[u8]),
+    pub(crate) remove: fn(&mut World, Entity),
+    pub(crate) snapshot: fn(&World, Entity) -> Option<Vec<u8>>,
+}
+
+/// type erased access to every replicated component, keyed by type name
+#[derive(Resource, Default)]
+pub struct ReplicationRegistry(HashMap<String, ReplicatedComponent>);
+
+impl ReplicationRegistry {
+    pub(crate) fn register<C: Component + Serialize + DeserializeOwned>(&mut self) {
+        self.0.insert(
+            type_name::<C>().to_string(),
+            ReplicatedComponent {
+                apply: apply_component::<C>,
+                remove: remove_component::<C>,
+                snapshot: snapshot_component::<C>,
+            },
+        );
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<ReplicatedComponent> {
+        self.0.get(name).copied()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &ReplicatedComponent)> {
+        self.0.iter()
+    }
+}
+
+/// entities whose `C` was last written by the network, so the change isn't echoed back
+#[derive(Resource)]
+pub(crate) struct ReceivedComponents<C: Component>(HashSet<Entity>, PhantomData<C>);
+
+impl<C: Component> Default for ReceivedComponents<C> {
+    fn default() -> Self {
+        Self(HashSet::new(), PhantomData)
+    }
+}
+
+impl<C: Component> ReceivedComponents<C> {
+    /// true once after the network wrote `entity`'s `C`
+    pub(crate) fn take(&mut self, entity: Entity) -> bool {
+        self.0.remove(&entity)
+    }
+}
+
+fn apply_component<C: Component + DeserializeOwned>(world: &mut World, entity: Entity, bytes: &[u8]) {
+    let component: C = match bincode::deserialize(bytes) {
+        Ok(component) => component,
+        Err(_) => return,
+    };
+    if let Some(mut world_entity) = world.get_entity_mut(entity) {
+        world_entity.insert(component);
+        world
+            .resource_mut::<ReceivedComponents<C>>()
+            .0
+            .insert(entity);
+    }
+}
+
+fn snapshot_component<C: Component + Serialize>(world: &World, entity: Entity) -> Option<Vec<u8>> {
+    world
+        .get::<C>(entity)
+        .map(|component| bincode::serialize(component).unwrap())
+}
+
+fn remove_component<C: Component>(world: &mut World, entity: Entity) {
+    if let Some(mut world_entity) = world.get_entity_mut(entity) {
+        if world_entity.remove::<C>().is_some() {
+            world
+                .resource_mut::<ReceivedComponents<C>>()
+                .0
+                .insert(entity);
+        }
+    }
+}
+
+fn component_changed<C: Component + Serialize>(
+    query: Query<(Entity, &C), (Changed<C>, With<Networked>)>,
+    authority: Query<(), With<Authority>>,
+    mut client: ResMut<Client>,
+    entity_map: Res<EntityMap>,
+    mut received: ResMut<ReceivedComponents<C>>,
+) {
+    for (entity, component) in query.iter() {
+        // cleared whoever has authority, a stale entry would swallow the first change after gaining it
+        if received.take(entity) || !authority.contains(entity) {
+            continue;
+        }
+        if let (Some(connection), Some(net_id)) = (client.get_connection_mut(), entity_map.get_by_left(&entity)) {
+            connection
+                .send_lek_msg(ComponentMsgServer::Changed(
+                    *net_id,
+                    type_name::<C>().to_string(),
+                    bincode::serialize(component).unwrap(),
+                ))
+                .unwrap()
+        }
+    }
+}
+
+fn component_removed<C: Component>(
+    mut removed: RemovedComponents<C>,
+    networked: Query<(), (With<Networked>, With<Authority>)>,
+    mut client: ResMut<Client>,
+    entity_map: Res<EntityMap>,
+    mut received: ResMut<ReceivedComponents<C>>,
+) {
+    for entity in removed.iter() {
+        if received.take(entity) || !networked.contains(entity) {
+            continue;
+        }
+        if let (Some(connection), Some(net_id)) = (client.get_connection_mut(), entity_map.get_by_left(&entity)) {
+            connection
+                .send_lek_msg(ComponentMsgServer::Removed(
+                    *net_id,
+                    type_name::<C>().to_string(),
+                ))
+                .unwrap()
+        }
+    }
+}
+
+fn server_component_changed<C: Component + Serialize>(
+    query: Query<(Entity, &NetId, &C), (Changed<C>, With<Networked>)>,
+    mut server: ResMut<Server>,
+    mut received: ResMut<ReceivedComponents<C>>,
+    interest: Option<Res<ClientInterest>>,
+    admitted: Res<AdmittedClients>,
+) {
+    let endpoint = match server.get_endpoint_mut() {
+        None => return,
+        Some(endpoint) => endpoint,
+    };
+    for (entity, net_id, component) in query.iter() {
+        if received.take(entity) {
+            continue;
+        }
+        let bytes = bincode::serialize(component).unwrap();
+        for client_id in admitted.of(endpoint) {
+            if !is_relevant(interest.as_deref(), client_id, *net_id) {
+                continue;
+            }
+            endpoint
+                .send_lek_msg(
+                    client_id,
+                    ComponentMsgClient::Changed(*net_id, type_name::<C>().to_string(), bytes.clone()),
+                )
+                .unwrap();
+        }
+    }
+}
+
+fn server_component_removed<C: Component>(
+    mut removed: RemovedComponents<C>,
+    query: Query<&NetId, With<Networked>>,
+    mut server: ResMut<Server>,
+    mut received: ResMut<ReceivedComponents<C>>,
+    interest: Option<Res<ClientInterest>>,
+    admitted: Res<AdmittedClients>,
+) {
+    let endpoint = match server.get_endpoint_mut() {
+        None => return,
+        Some(endpoint) => endpoint,
+    };
+    for entity in removed.iter() {
+        if received.take(entity) {
+            continue;
+        }
+        if let Ok(net_id) = query.get(entity) {
+            for client_id in admitted.of(endpoint) {
+                if !is_relevant(interest.as_deref(), client_id, *net_id) {
+                    continue;
+                }
+                endpoint
+                    .send_lek_msg(
+                        client_id,
+                        ComponentMsgClient::Removed(*net_id, type_name::<C>().to_string()),
+                    )
+                    .unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use crate::networking::replicated_resource::ResourceRegistry;
+use bevy_app::App;
+use bevy_ecs::prelude::World;
+use bevy_quinnet::shared::channel::ChannelType;
+use leknet::{ClientMessage, TypeName};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ResourceMsgClient {
+    Changed(String, Vec<u8>),
+}
+
+impl TypeName for ResourceMsgClient {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::ResourceMsgClient".to_string()
+    }
+}
+
+impl ClientMessage for ResourceMsgClient {
+    fn client(self, world: &mut World) {
+        match self {
+            ResourceMsgClient::Changed(name, bytes) => {
+                if let Some(replicated) = world.resource::<ResourceRegistry>().get(&name) {
+                    (replicated.apply)(world, &bytes);
+                }
+            }
+        }
+    }
+
+    fn _client(world: &mut World, msg_bytes: &[u8]) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .client(world)
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            ResourceMsgClient::Changed(_, _) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<ResourceRegistry>();
+    }
+}
@@ -0,0 +1,46 @@
+use crate::networking::replicated_resource::ResourceRegistry;
+use bevy_app::App;
+use bevy_ecs::prelude::World;
+use bevy_quinnet::shared::channel::ChannelType;
+use bevy_quinnet::shared::ClientId;
+use leknet::{ServerMessage, TypeName};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ResourceMsgServer {
+    RequestChange(String, Vec<u8>),
+}
+
+impl TypeName for ResourceMsgServer {
+    fn get_type_name() -> String {
+        "stereokit_bevy::networking::ResourceMsgServer".to_string()
+    }
+}
+
+impl ServerMessage for ResourceMsgServer {
+    fn server(self, world: &mut World, client_id: ClientId) {
+        match self {
+            ResourceMsgServer::RequestChange(name, bytes) => {
+                if let Some(replicated) = world.resource::<ResourceRegistry>().get(&name) {
+                    (replicated.request)(world, client_id, &bytes);
+                }
+            }
+        }
+    }
+
+    fn _server(world: &mut World, msg_bytes: &[u8], client_id: ClientId) {
+        bincode::deserialize::<Self>(msg_bytes)
+            .unwrap()
+            .server(world, client_id);
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match self {
+            ResourceMsgServer::RequestChange(_, _) => ChannelType::OrderedReliable,
+        }
+    }
+
+    fn plugin(app: &mut App) {
+        app.init_resource::<ResourceRegistry>();
+    }
+}
@@ -0,0 +1,91 @@
+use crate::networking::admin_server::AdminConsole;
+use bevy_app::{App, AppExit};
+use bevy_ecs::event::{Events, ManualEventReader};
+use bevy_ecs::prelude::{ResMut, Resource};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// how many times per second the headless server updates
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ServerTickRate(pub u32);
+
+impl Default for ServerTickRate {
+    fn default() -> Self {
+        Self(60)
+    }
+}
+
+impl ServerTickRate {
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.0.max(1) as f64)
+    }
+}
+
+/// number of the server update currently running, stamped on outgoing state
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ServerTick(pub u64);
+
+/// counts updates of a server that isn't run by `server_loop`, e.g. a listen server
+pub(crate) fn advance_tick(mut tick: ResMut<ServerTick>) {
+    tick.0 += 1;
+}
+
+/// updates the app at `ServerTickRate` until `AppExit` is sent or the process gets SIGINT
+pub(crate) fn server_loop(mut app: App) {
+    // only a headless server owns its terminal, a listen server's stdin belongs to the client
+    app.insert_resource(AdminConsole::spawn());
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let interrupted = interrupted.clone();
+        if let Err(e) = ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst)) {
+            println!("couldn't install the SIGINT handler: {}", e);
+        }
+    }
+    let mut app_exit = ManualEventReader::<AppExit>::default();
+    let mut next_tick = Instant::now();
+    loop {
+        if interrupted.swap(false, Ordering::SeqCst) {
+            // one more update so everything listening for AppExit, e.g. saving, gets to run
+            app.world.send_event(AppExit);
+        }
+        let tick_duration = app
+            .world
+            .get_resource::<ServerTickRate>()
+            .copied()
+            .unwrap_or_default()
+            .tick_duration();
+        let started = Instant::now();
+        app.update();
+        let tick = {
+            let mut tick = app.world.get_resource_or_insert_with(ServerTick::default);
+            tick.0 += 1;
+            tick.0
+        };
+        if let Some(app_exit_events) = app.world.get_resource::<Events<AppExit>>() {
+            if app_exit.iter(app_exit_events).last().is_some() {
+                break;
+            }
+        }
+
+        let elapsed = started.elapsed();
+        if elapsed > tick_duration {
+            println!(
+                "server tick {} overran by {:?}",
+                tick,
+                elapsed - tick_duration
+            );
+            // don't try to catch up, that would only make the next ticks overrun too
+            next_tick = Instant::now();
+            continue;
+        }
+        next_tick += tick_duration;
+        let now = Instant::now();
+        if next_tick > now {
+            thread::sleep(next_tick - now);
+        } else {
+            next_tick = now;
+        }
+    }
+}
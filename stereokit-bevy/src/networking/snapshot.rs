@@ -0,0 +1,104 @@
+use crate::networking::component_client::ComponentMsgClient;
+use crate::networking::hierarchy_client::HierarchyMsgClient;
+use crate::networking::interest::InterestSettings;
+use crate::networking::model_client::ModelMsgClient;
+use crate::networking::player_client::PlayerMsgClient;
+use crate::networking::replication::ReplicationRegistry;
+use crate::networking::{ModelData, Player};
+use crate::ModelInfo;
+use bevy_ecs::event::{Events, ManualEventReader};
+use bevy_ecs::prelude::{Entity, Local, With, World};
+use bevy_hierarchy::Parent;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::ClientId;
+use bevy_transform::prelude::Transform;
+use leknet::{ClientAdmitted, EntityMap, LekServer, NetId, Networked};
+use stereokit::{Color128, RenderLayer};
+
+/// sends everything the server knows about `entity` to `client_id`, as if it had just been spawned
+pub(crate) fn send_entity_snapshot(world: &mut World, client_id: ClientId, entity: Entity) {
+    let world_entity = match world.get_entity(entity) {
+        None => return,
+        Some(world_entity) => world_entity,
+    };
+    let net_id = match world_entity.get::<NetId>() {
+        None => return,
+        Some(net_id) => *net_id,
+    };
+    let model_added = match (
+        world_entity.get::<ModelInfo>(),
+        world_entity.get::<Transform>(),
+        world_entity.get::<Color128>(),
+        world_entity.get::<RenderLayer>(),
+    ) {
+        (Some(model_info), Some(transform), Some(color128), Some(render_layer)) => {
+            Some(ModelMsgClient::ModelAdded(
+                net_id,
+                ModelData {
+                    model_info: model_info.clone(),
+                    transform: *transform,
+                    color128: *color128,
+                    render_layer: *render_layer,
+                },
+            ))
+        }
+        _ => None,
+    };
+    let player_added = match (world_entity.contains::<Player>(), world_entity.get::<Transform>()) {
+        (true, Some(transform)) => Some(PlayerMsgClient::PlayerAdded(net_id, *transform)),
+        _ => None,
+    };
+    let parent_changed = world_entity
+        .get::<Parent>()
+        .and_then(|parent| world.resource::<EntityMap>().get_by_left(&parent.get()).copied())
+        .map(|parent| HierarchyMsgClient::ParentChanged(net_id, Some(parent)));
+    let components: Vec<ComponentMsgClient> = world
+        .resource::<ReplicationRegistry>()
+        .iter()
+        .filter_map(|(name, replicated)| {
+            (replicated.snapshot)(world, entity)
+                .map(|bytes| ComponentMsgClient::Changed(net_id, name.clone(), bytes))
+        })
+        .collect();
+
+    let mut server = world.resource_mut::<Server>();
+    let endpoint = match server.get_endpoint_mut() {
+        None => return,
+        Some(endpoint) => endpoint,
+    };
+    if let Some(model_added) = model_added {
+        endpoint.send_lek_msg(client_id, model_added).unwrap();
+    }
+    if let Some(player_added) = player_added {
+        endpoint.send_lek_msg(client_id, player_added).unwrap();
+    }
+    for component in components {
+        endpoint.send_lek_msg(client_id, component).unwrap();
+    }
+    if let Some(parent_changed) = parent_changed {
+        endpoint.send_lek_msg(client_id, parent_changed).unwrap();
+    }
+}
+
+/// sends the whole networked world to newly admitted clients, unless interest management decides what they see
+pub(crate) fn new_client_snapshot(
+    world: &mut World,
+    mut admitted: Local<ManualEventReader<ClientAdmitted>>,
+) {
+    let client_ids: Vec<ClientId> = admitted
+        .iter(world.resource::<Events<ClientAdmitted>>())
+        .map(|admitted| admitted.client_id)
+        .collect();
+    if client_ids.is_empty() || world.contains_resource::<InterestSettings>() {
+        return;
+    }
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, With<Networked>>()
+        .iter(world)
+        .collect();
+    for client_id in client_ids {
+        for entity in entities.iter() {
+            send_entity_snapshot(world, client_id, *entity);
+        }
+    }
+}
@@ -50,3 +50,804 @@ fn sync_example_model(sk: Res<Sk>, mut query: Query<(&RightHand, &mut Transform)
         transform.rotation = temp.rotation;
     }
 }
+
+#[test]
+fn model_delta_round_trip() {
+    use crate::networking::{ModelData2, ModelDelta, QuantizationSettings};
+
+    let baseline = ModelData2 {
+        transform: Transform::from_xyz(1.0, 2.0, 3.0),
+        color128: stereokit::named_colors::AQUAMARINE,
+        render_layer: Default::default(),
+    };
+    let mut moved = baseline.clone();
+    moved.transform.translation = Vec3::new(4.0, 5.0, 6.0);
+
+    let full = bincode::serialize(&ModelDelta::between(None, &moved)).unwrap();
+    let delta = bincode::serialize(&ModelDelta::between(Some(&baseline), &moved)).unwrap();
+    // the mask plus three floats
+    assert_eq!(delta.len(), 1 + 3 * 4);
+    assert!(delta.len() < full.len());
+
+    let settings = QuantizationSettings::default();
+    let decoded: ModelDelta = bincode::deserialize(&delta).unwrap();
+    let applied = decoded.apply(Some(&baseline), &settings).unwrap();
+    assert_eq!(applied.transform, moved.transform);
+    assert!(decoded.apply(None, &settings).is_none());
+    let decoded_full: ModelDelta = bincode::deserialize(&full).unwrap();
+    assert_eq!(decoded_full.apply(None, &settings).unwrap().transform, moved.transform);
+}
+
+#[test]
+fn compact_transform_position_precision() {
+    use crate::networking::{CompactTransform, QuantizationSettings};
+
+    let settings = QuantizationSettings::default();
+    let precision = settings.precision();
+    for translation in [
+        Vec3::ZERO,
+        Vec3::new(1.234, -0.5, 17.25),
+        Vec3::new(-31.9, 31.9, 0.001),
+        settings.min,
+        settings.max,
+    ] {
+        let transform = Transform::from_translation(translation);
+        let decoded = CompactTransform::encode(&transform, &settings).decode(&settings);
+        let error = (decoded.translation - translation).abs();
+        assert!(error.cmple(precision + 1e-5).all(), "{:?} came back as {:?}", translation, decoded.translation);
+    }
+}
+
+#[test]
+fn compact_transform_clamps_to_bounds() {
+    use crate::networking::{CompactTransform, QuantizationSettings};
+
+    let settings = QuantizationSettings::default();
+    let transform = Transform::from_xyz(1000.0, -1000.0, 0.0);
+    let decoded = CompactTransform::encode(&transform, &settings).decode(&settings);
+    assert!((decoded.translation.x - settings.max.x).abs() < 1e-3);
+    assert!((decoded.translation.y - settings.min.y).abs() < 1e-3);
+}
+
+#[test]
+fn compact_transform_rotation_precision() {
+    use crate::networking::{CompactTransform, QuantizationSettings};
+    use glam::Quat;
+
+    let settings = QuantizationSettings::default();
+    for rotation in [
+        Quat::IDENTITY,
+        Quat::from_rotation_x(1.0),
+        Quat::from_rotation_y(-2.5),
+        Quat::from_rotation_z(std::f32::consts::PI),
+        Quat::from_euler(glam::EulerRot::XYZ, 0.3, -1.2, 2.9),
+        -Quat::from_rotation_y(0.7),
+    ] {
+        let transform = Transform::from_rotation(rotation);
+        let decoded = CompactTransform::encode(&transform, &settings).decode(&settings);
+        let angle = decoded.rotation.angle_between(rotation);
+        assert!(angle < 0.005, "{:?} came back {} radians off", rotation, angle);
+    }
+}
+
+#[test]
+fn compact_transform_scale() {
+    use crate::networking::{CompactTransform, QuantizationSettings};
+
+    let settings = QuantizationSettings::default();
+    for (scale, size) in [
+        (Vec3::ONE, 11),
+        (Vec3::splat(0.1), 15),
+        (Vec3::new(1.0, 2.0, 3.0), 23),
+    ] {
+        let transform = Transform::from_scale(scale);
+        let compact = CompactTransform::encode(&transform, &settings);
+        let bytes = bincode::serialize(&compact).unwrap();
+        assert_eq!(bytes.len(), size);
+        let decoded: CompactTransform = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(decoded, compact);
+        assert_eq!(decoded.decode(&settings).scale, scale);
+    }
+}
+
+#[test]
+fn admin_command_parse() {
+    use crate::networking::AdminCommand;
+    use leknet::NetId;
+
+    assert_eq!(AdminCommand::parse("list"), Ok(AdminCommand::List));
+    assert_eq!(AdminCommand::parse("  kick 3 "), Ok(AdminCommand::Kick(3)));
+    assert_eq!(AdminCommand::parse("ban some one"), Ok(AdminCommand::Ban("some one".to_string())));
+    assert_eq!(AdminCommand::parse("despawn 12"), Ok(AdminCommand::Despawn(NetId(12))));
+    assert_eq!(
+        AdminCommand::parse("notice server restarts in 5 minutes"),
+        Ok(AdminCommand::Notice("server restarts in 5 minutes".to_string()))
+    );
+    assert_eq!(
+        AdminCommand::parse("role 4 Moderator"),
+        Ok(AdminCommand::SetRole(4, leknet::Role::Moderator))
+    );
+    assert_eq!(AdminCommand::parse("mute 5"), Ok(AdminCommand::Mute("5".to_string())));
+    assert!(AdminCommand::parse("unmute").is_err());
+    assert!(AdminCommand::parse("role 4 king").is_err());
+    assert!(AdminCommand::parse("kick someone").is_err());
+    assert!(AdminCommand::parse("notice").is_err());
+    assert!(AdminCommand::parse("fly").is_err());
+}
+
+#[test]
+fn admin_bans_by_address_and_identity() {
+    use crate::networking::admin_server::load_ban_list;
+    use crate::networking::{run_admin_command, AdminCommand, AdminSettings};
+    use bevy_ecs::prelude::{Res, ResMut};
+    use bevy_ecs::system::SystemState;
+    use leknet::{AdmittedClients, BanList, PeerAddresses};
+    use std::net::IpAddr;
+
+    let path = std::env::temp_dir().join(format!("stereokit-bevy-bans-{}.txt", std::process::id()));
+    let mut world = bevy_ecs::prelude::World::new();
+    world.insert_resource(AdminSettings {
+        token: None,
+        ban_list_path: Some(path.clone()),
+    });
+    world.init_resource::<BanList>();
+    world.init_resource::<AdmittedClients>();
+    world.init_resource::<PeerAddresses>();
+
+    let address: IpAddr = "203.0.113.7".parse().unwrap();
+    run_admin_command(&mut world, AdminCommand::Ban("203.0.113.7".to_string()));
+    run_admin_command(&mut world, AdminCommand::Ban("mallory".to_string()));
+    assert!(world.resource::<BanList>().addresses.contains(&address));
+    assert!(world.resource::<BanList>().identities.contains("mallory"));
+
+    // bans.txt holds both kinds and they load back into the right set
+    let mut loaded = bevy_ecs::prelude::World::new();
+    loaded.insert_resource(world.resource::<AdminSettings>().clone());
+    loaded.init_resource::<BanList>();
+    let mut system_state: SystemState<(Res<AdminSettings>, ResMut<BanList>)> = SystemState::new(&mut loaded);
+    let (settings, ban_list) = system_state.get_mut(&mut loaded);
+    load_ban_list(settings, ban_list);
+    assert!(loaded.resource::<BanList>().addresses.contains(&address));
+    assert!(loaded.resource::<BanList>().identities.contains("mallory"));
+    assert_eq!(loaded.resource::<BanList>().identities.len(), 1);
+
+    assert_eq!(run_admin_command(&mut world, AdminCommand::Unban("203.0.113.7".to_string())), "unbanned 203.0.113.7");
+    assert!(world.resource::<BanList>().addresses.is_empty());
+    assert!(world.resource::<BanList>().identities.contains("mallory"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn permission_table_allows() {
+    use crate::networking::{Permission, PermissionTable};
+    use leknet::Role;
+
+    let table = PermissionTable::default();
+    assert!(!table.allows(Role::Spectator, Permission::Voice));
+    assert!(table.allows(Role::Guest, Permission::Voice));
+    assert!(!table.allows(Role::Guest, Permission::SpawnModels));
+    assert!(table.allows(Role::Member, Permission::SpawnModels));
+    assert!(!table.allows(Role::Member, Permission::DespawnAny));
+    assert!(table.allows(Role::Moderator, Permission::DespawnAny));
+    assert!(table.allows(Role::Host, Permission::DespawnAny));
+    // roles missing from a custom table have no permissions
+    let table = PermissionTable(std::collections::HashMap::new());
+    assert!(!table.allows(Role::Host, Permission::Voice));
+}
+
+#[test]
+fn admin_mute_and_despawn() {
+    use crate::networking::{is_muted, run_admin_command, AdminCommand, MutedClients};
+    use leknet::{AdmittedClients, EntityMap, NetId};
+
+    let mut world = bevy_ecs::prelude::World::new();
+    world.init_resource::<AdmittedClients>();
+    world.init_resource::<MutedClients>();
+    world.insert_resource(EntityMap(bimap::BiHashMap::new()));
+    world.resource_mut::<AdmittedClients>().0.insert(1, "alice".to_string());
+
+    run_admin_command(&mut world, AdminCommand::Mute("1".to_string()));
+    assert!(is_muted(&world, 1));
+    // the mute sticks to the identity, not to the connection
+    world.resource_mut::<AdmittedClients>().0.remove(&1);
+    world.resource_mut::<AdmittedClients>().0.insert(2, "alice".to_string());
+    assert!(is_muted(&world, 2));
+    run_admin_command(&mut world, AdminCommand::Unmute("alice".to_string()));
+    assert!(!is_muted(&world, 2));
+
+    // an entry whose entity is already gone is reported instead of panicking
+    let entity = world.spawn_empty().id();
+    world.resource_mut::<EntityMap>().insert(entity, NetId(3));
+    world.despawn(entity);
+    assert_eq!(run_admin_command(&mut world, AdminCommand::Despawn(NetId(3))), "nothing has NetId(3)");
+}
+
+#[test]
+fn replication_registry_apply_and_remove() {
+    use crate::networking::replication::{ReceivedComponents, ReplicationRegistry};
+    use bevy_ecs::prelude::World;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    let mut registry = ReplicationRegistry::default();
+    registry.register::<Health>();
+    let replicated = registry.get(std::any::type_name::<Health>()).unwrap();
+    let mut world = World::new();
+    world.insert_resource(ReceivedComponents::<Health>::default());
+    let entity = world.spawn_empty().id();
+
+    let bytes = bincode::serialize(&Health(3)).unwrap();
+    (replicated.apply)(&mut world, entity, &bytes);
+    assert_eq!(world.get::<Health>(entity), Some(&Health(3)));
+    assert_eq!((replicated.snapshot)(&world, entity), Some(bytes));
+    // written by the network, so the change systems skip it exactly once instead of echoing it
+    let mut received = world.resource_mut::<ReceivedComponents<Health>>();
+    assert!(received.take(entity));
+    assert!(!received.take(entity));
+
+    (replicated.remove)(&mut world, entity);
+    assert_eq!(world.get::<Health>(entity), None);
+    assert_eq!((replicated.snapshot)(&world, entity), None);
+    assert!(world.resource_mut::<ReceivedComponents<Health>>().take(entity));
+    // nothing was removed, so there's nothing to suppress
+    (replicated.remove)(&mut world, entity);
+    assert!(!world.resource_mut::<ReceivedComponents<Health>>().take(entity));
+
+    // malformed bytes are ignored
+    (replicated.apply)(&mut world, entity, &[1]);
+    assert_eq!(world.get::<Health>(entity), None);
+    assert!(!world.resource_mut::<ReceivedComponents<Health>>().take(entity));
+}
+
+#[test]
+fn interest_radius_and_groups() {
+    use crate::networking::interest::{relevant, Viewer};
+    use crate::networking::{InterestGroups, InterestSettings};
+    use std::collections::HashSet;
+
+    let settings = InterestSettings { radius: 5.0 };
+    let viewer = Some(Viewer {
+        translation: Vec3::ZERO,
+        groups: HashSet::from([1]),
+    });
+    assert!(relevant(&settings, &viewer, Some(Vec3::new(5.0, 0.0, 0.0)), None));
+    assert!(!relevant(&settings, &viewer, Some(Vec3::new(5.1, 0.0, 0.0)), None));
+    // a shared group is relevant at any distance, another group isn't
+    let far = Some(Vec3::splat(100.0));
+    assert!(relevant(&settings, &viewer, far, Some(&InterestGroups(HashSet::from([1, 2])))));
+    assert!(!relevant(&settings, &viewer, far, Some(&InterestGroups(HashSet::from([2])))));
+    // entities without a position, and clients without a player yet, see everything
+    assert!(relevant(&settings, &viewer, None, None));
+    assert!(relevant(&settings, &None, far, None));
+}
+
+#[test]
+fn interest_children_follow_their_root() {
+    use crate::networking::interest::{relevant_entities, root_entities, InterestEntity, Viewer};
+    use crate::networking::InterestSettings;
+    use bevy_ecs::prelude::Entity;
+    use leknet::NetId;
+    use std::collections::HashSet;
+
+    let settings = InterestSettings { radius: 5.0 };
+    let viewer = Some(Viewer {
+        translation: Vec3::ZERO,
+        groups: HashSet::new(),
+    });
+    let entity = |index: u32, x: f32, spawned_by: Option<u64>, parent: Option<u32>| InterestEntity {
+        entity: Entity::from_raw(index),
+        net_id: NetId(index as u64),
+        translation: Some(Vec3::new(x, 0.0, 0.0)),
+        groups: None,
+        spawned_by,
+        parent: parent.map(Entity::from_raw),
+    };
+    let mut entities = vec![
+        entity(0, 1.0, None, None),
+        // far away itself, but its parent is close
+        entity(1, 100.0, None, Some(0)),
+        entity(2, 100.0, None, Some(1)),
+        // spawned by the viewer, so always known
+        entity(3, 100.0, Some(7), None),
+    ];
+    let roots = root_entities(&entities);
+    assert_eq!(roots[&Entity::from_raw(2)], Entity::from_raw(0));
+    let relevant = relevant_entities(&settings, 7, &viewer, &entities, &roots);
+    assert_eq!(relevant, HashSet::from([NetId(0), NetId(1), NetId(2), NetId(3)]));
+
+    // the root leaving takes its children with it, even ones close to the viewer
+    entities[0].translation = Some(Vec3::splat(100.0));
+    entities[2].translation = Some(Vec3::ZERO);
+    let relevant = relevant_entities(&settings, 7, &viewer, &entities, &roots);
+    assert_eq!(relevant, HashSet::from([NetId(3)]));
+}
+
+fn temp_save_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("stereokit-bevy-{}-{}.save", name, std::process::id()))
+}
+
+fn persistence_world(path: &std::path::Path) -> bevy_ecs::prelude::World {
+    use crate::networking::hierarchy_client::{PendingParents, ReceivedParents};
+    use crate::networking::{ReplicationRegistry, WorldPersistence};
+    use leknet::{EntityMap, NetIdAllocator};
+
+    let mut world = bevy_ecs::prelude::World::new();
+    world.insert_resource(WorldPersistence::new(path));
+    world.insert_resource(EntityMap(bimap::BiHashMap::new()));
+    world.insert_resource(NetIdAllocator::default());
+    world.insert_resource(ReplicationRegistry::default());
+    world.insert_resource(PendingParents::default());
+    world.insert_resource(ReceivedParents::default());
+    world
+}
+
+#[test]
+fn removing_a_missing_parent_leaves_no_received_entry() {
+    use crate::networking::hierarchy_client::{apply_pending_parents, PendingParents, ReceivedParents};
+    use bevy_ecs::schedule::Schedule;
+    use bevy_hierarchy::BuildWorldChildren;
+    use leknet::{EntityMap, NetId};
+
+    let mut world = bevy_ecs::prelude::World::new();
+    world.insert_resource(EntityMap(bimap::BiHashMap::new()));
+    world.insert_resource(PendingParents::default());
+    world.insert_resource(ReceivedParents::default());
+    let parent = world.spawn(NetId(1)).id();
+    let child = world.spawn(NetId(2)).id();
+    let orphan = world.spawn(NetId(3)).id();
+    world.entity_mut(child).set_parent(parent);
+    for (entity, net_id) in [(parent, 1), (child, 2), (orphan, 3)] {
+        world.resource_mut::<EntityMap>().insert(entity, NetId(net_id));
+    }
+    world.resource_mut::<PendingParents>().defer(NetId(2), None);
+    world.resource_mut::<PendingParents>().defer(NetId(3), None);
+    let mut schedule = Schedule::new();
+    schedule.add_system(apply_pending_parents);
+    schedule.run(&mut world);
+
+    // the orphan's parent didn't change, an entry for it would swallow its next real change
+    let received = &world.resource::<ReceivedParents>().0;
+    assert!(received.contains(&child));
+    assert!(!received.contains(&orphan));
+}
+
+#[test]
+fn world_save_round_trip() {
+    use crate::networking::hierarchy_client::apply_pending_parents;
+    use crate::networking::persistence::load_world;
+    use crate::networking::save_world;
+    use bevy_ecs::schedule::Schedule;
+    use bevy_hierarchy::{BuildWorldChildren, Parent};
+    use leknet::{EntityMap, NetId, NetIdAllocator};
+    use stereokit::{Color128, RenderLayer};
+
+    let path = temp_save_path("round-trip");
+    let mut world = persistence_world(&path);
+    let spawn = |world: &mut bevy_ecs::prelude::World, net_id: NetId, x: f32| {
+        let entity = world
+            .spawn((net_id, Networked))
+            .insert((
+                ModelInfo::Cube(Vec3::splat(0.5)),
+                Transform::from_xyz(x, 1.0, 0.0),
+                Color128::new(0.1, 0.2, 0.3, 1.0),
+                RenderLayer::LAYER1,
+            ))
+            .id();
+        world.resource_mut::<EntityMap>().insert(entity, net_id);
+        entity
+    };
+    let parent = spawn(&mut world, NetId(10), 1.0);
+    let child = spawn(&mut world, NetId(11), 2.0);
+    world.entity_mut(child).set_parent(parent);
+    save_world(&mut world).unwrap();
+
+    let mut loaded = persistence_world(&path);
+    load_world(&mut loaded);
+    let mut schedule = Schedule::new();
+    schedule.add_system(apply_pending_parents);
+    schedule.run(&mut loaded);
+    std::fs::remove_file(&path).unwrap();
+
+    let entity_map = loaded.resource::<EntityMap>();
+    let loaded_parent = *entity_map.get_by_right(&NetId(10)).unwrap();
+    let loaded_child = *entity_map.get_by_right(&NetId(11)).unwrap();
+    assert_eq!(loaded.get::<Transform>(loaded_child), Some(&Transform::from_xyz(2.0, 1.0, 0.0)));
+    match loaded.get::<ModelInfo>(loaded_child) {
+        Some(ModelInfo::Cube(size)) => assert_eq!(*size, Vec3::splat(0.5)),
+        model_info => panic!("expected a cube, got {:?}", model_info),
+    }
+    assert_eq!(loaded.get::<Parent>(loaded_child).map(|parent| parent.get()), Some(loaded_parent));
+    // saved ids are never handed out again
+    assert!(loaded.resource_mut::<NetIdAllocator>().allocate().0 > 11);
+}
+
+#[test]
+fn world_save_with_other_version_is_ignored() {
+    use crate::networking::persistence::load_world;
+    use crate::networking::WORLD_SAVE_VERSION;
+    use leknet::EntityMap;
+
+    let path = temp_save_path("other-version");
+    let no_entities: Vec<()> = vec![];
+    std::fs::write(&path, bincode::serialize(&(WORLD_SAVE_VERSION + 1, no_entities)).unwrap()).unwrap();
+    let mut world = persistence_world(&path);
+    load_world(&mut world);
+    assert!(world.resource::<EntityMap>().is_empty());
+
+    // a file that isn't a save at all is ignored too
+    std::fs::write(&path, [1, 2]).unwrap();
+    load_world(&mut world);
+    std::fs::remove_file(&path).unwrap();
+    assert!(world.resource::<EntityMap>().is_empty());
+    assert_eq!(world.entities().len(), 0);
+}
+
+#[test]
+fn ownership_grant_and_transfer() {
+    use crate::networking::ownership_client::OwnershipMsgClient;
+    use crate::networking::ownership_server::transfer_ownership;
+    use crate::networking::{has_authority, Authority, Owner, Player};
+    use bevy_ecs::prelude::World;
+    use leknet::{ClientMessage, EntityMap, NetId};
+
+    let mut server = World::new();
+    server.insert_resource(EntityMap(bimap::BiHashMap::new()));
+    let model = server.spawn((NetId(1), Networked, Owner(1))).id();
+    let player = server.spawn((NetId(2), Networked, Player, Owner(1))).id();
+    server.resource_mut::<EntityMap>().insert(model, NetId(1));
+    server.resource_mut::<EntityMap>().insert(player, NetId(2));
+
+    assert!(has_authority(&server, 1, NetId(1)));
+    assert_eq!(transfer_ownership(&mut server, 2, NetId(1)), Some(Some(Owner(1))));
+    assert!(has_authority(&server, 2, NetId(1)));
+    assert!(!has_authority(&server, 1, NetId(1)));
+    // asking again changes nothing, players and unknown ids are never handed over
+    assert_eq!(transfer_ownership(&mut server, 2, NetId(1)), None);
+    assert_eq!(transfer_ownership(&mut server, 2, NetId(2)), None);
+    assert!(has_authority(&server, 1, NetId(2)));
+    assert_eq!(transfer_ownership(&mut server, 2, NetId(3)), None);
+
+    let mut client = World::new();
+    client.insert_resource(EntityMap(bimap::BiHashMap::new()));
+    let entity = client.spawn((NetId(1), Networked)).id();
+    client.resource_mut::<EntityMap>().insert(entity, NetId(1));
+    OwnershipMsgClient::Granted(NetId(1)).client(&mut client);
+    assert!(client.get::<Authority>(entity).is_some());
+    OwnershipMsgClient::Revoked(NetId(1)).client(&mut client);
+    assert!(client.get::<Authority>(entity).is_none());
+}
+
+#[test]
+fn model_validation() {
+    use crate::networking::validation::validate_model_msg;
+    use crate::networking::{ModelData, ModelMsgServer, Validation, ValidationLimits, WireModelData, WireTransform};
+    use bevy_ecs::prelude::World;
+    use leknet::{EntityMap, NetId};
+
+    let mut world = World::new();
+    world.insert_resource(EntityMap(bimap::BiHashMap::new()));
+    world.insert_resource(ValidationLimits {
+        max_player_step: 10.0,
+        max_model_size: 2.0,
+    });
+    let added = |model_info: ModelInfo, scale: Vec3| {
+        ModelMsgServer::ModelAdded(
+            NetId(1),
+            ModelData {
+                model_info,
+                transform: Transform::from_scale(scale),
+                color128: stereokit::named_colors::AQUAMARINE,
+                render_layer: Default::default(),
+            },
+        )
+    };
+
+    let mut msg = added(ModelInfo::Cube(Vec3::ONE), Vec3::ONE);
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Accept);
+    let mut msg = added(ModelInfo::Cube(Vec3::new(1.0, -1.0, 1.0)), Vec3::ONE);
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Reject);
+    let mut msg = added(ModelInfo::Cube(Vec3::ONE), Vec3::new(1.0, 0.0, 1.0));
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Reject);
+    let mut msg = added(ModelInfo::Cube(Vec3::ONE), Vec3::splat(f32::NAN));
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Reject);
+    let mem = ModelInfo::Mem {
+        name: "model".to_string(),
+        mem: vec![0; 16],
+    };
+    let mut msg = added(mem, Vec3::ONE);
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Reject);
+
+    // a small cube scaled up past the limit is shrunk so the drawn cube fits
+    let mut msg = added(ModelInfo::Cube(Vec3::ONE), Vec3::splat(4.0));
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Clamped);
+    match msg {
+        ModelMsgServer::ModelAdded(_, ModelData { model_info: ModelInfo::Cube(size), .. }) => {
+            assert_eq!(size, Vec3::splat(0.5))
+        }
+        _ => unreachable!(),
+    }
+
+    let entity = world.spawn(ModelInfo::Cube(Vec3::ONE)).id();
+    world.resource_mut::<EntityMap>().insert(entity, NetId(1));
+    let changed = |scale: Vec3| {
+        ModelMsgServer::ModelChanged(
+            NetId(1),
+            WireModelData {
+                transform: WireTransform::Raw(Transform::from_scale(scale)),
+                color128: stereokit::named_colors::AQUAMARINE,
+                render_layer: Default::default(),
+            },
+            0,
+        )
+    };
+    let mut msg = changed(Vec3::splat(1.5));
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Accept);
+    let mut msg = changed(Vec3::new(1.0, 1.0, -1.0));
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Reject);
+    let mut msg = changed(Vec3::new(1.0, 3.0, 1.0));
+    assert_eq!(validate_model_msg(&world, 1, &mut msg), Validation::Clamped);
+    match msg {
+        ModelMsgServer::ModelChanged(_, WireModelData { transform: WireTransform::Raw(transform), .. }, _) => {
+            assert_eq!(transform.scale, Vec3::new(1.0, 2.0, 1.0))
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn pending_changes_wait_for_their_entity() {
+    use crate::networking::id_server::GrantedIds;
+    use crate::networking::pending::{defer_change, PendingChange, PendingChanges};
+    use bevy_ecs::prelude::World;
+    use leknet::{EntityMap, NetId};
+    use std::time::Duration;
+
+    let mut world = World::new();
+    world.insert_resource(EntityMap(bimap::BiHashMap::new()));
+    world.insert_resource(GrantedIds::default());
+    world.insert_resource(PendingChanges::default());
+    world.resource_mut::<GrantedIds>().insert(1, 100..200);
+
+    let moved = |x: f32| PendingChange::Player(Transform::from_xyz(x, 0.0, 0.0));
+    // only ids the sender may still spawn are kept, everything else is handled right away
+    assert!(defer_change(&mut world, 1, NetId(100), moved(1.0)));
+    assert!(!defer_change(&mut world, 2, NetId(101), moved(1.0)));
+    assert!(!defer_change(&mut world, 1, NetId(300), moved(1.0)));
+    let entity = world.spawn(NetId(150)).id();
+    world.resource_mut::<EntityMap>().insert(entity, NetId(150));
+    assert!(!defer_change(&mut world, 1, NetId(150), moved(1.0)));
+
+    // a newer change replaces the older one
+    assert!(defer_change(&mut world, 1, NetId(100), moved(2.0)));
+    let mut pending = world.resource_mut::<PendingChanges>();
+    match pending.take(NetId(100)) {
+        Some((1, PendingChange::Player(transform))) => assert_eq!(transform.translation.x, 2.0),
+        _ => unreachable!(),
+    }
+    assert!(pending.take(NetId(100)).is_none());
+
+    assert!(defer_change(&mut world, 1, NetId(101), moved(1.0)));
+    let mut pending = world.resource_mut::<PendingChanges>();
+    pending.expire(Duration::from_secs(5));
+    assert!(pending.take(NetId(101)).is_some());
+    drop(pending);
+    assert!(defer_change(&mut world, 1, NetId(101), moved(1.0)));
+    let mut pending = world.resource_mut::<PendingChanges>();
+    pending.expire(Duration::ZERO);
+    assert!(pending.take(NetId(101)).is_none());
+}
+
+#[test]
+fn granted_ids_count_unused_ranges() {
+    use crate::networking::id_server::GrantedIds;
+    use leknet::NetId;
+
+    let mut granted = GrantedIds::default();
+    assert_eq!(granted.unused(1), 0);
+    granted.insert(1, 0..100);
+    granted.insert(1, 100..200);
+    assert_eq!(granted.unused(1), 2);
+    granted.claim(1, NetId(42));
+    assert_eq!(granted.unused(1), 1);
+    // a lower id spawned later doesn't undo the claim
+    granted.claim(1, NetId(150));
+    granted.claim(1, NetId(7));
+    assert_eq!(granted.unused(1), 0);
+    granted.insert(1, 200..300);
+    assert_eq!(granted.unused(1), 1);
+    assert_eq!(granted.unused(2), 0);
+}
+
+#[test]
+fn transform_buffer_sample() {
+    use crate::networking::{ServerStamp, TransformBuffer};
+
+    let stamp = |time: f64| ServerStamp { time, tick: 0 };
+    let mut buffer = TransformBuffer::default();
+    assert!(buffer.sample(1.0, 0.25).is_none());
+    buffer.push(stamp(1.0), Transform::from_xyz(0.0, 0.0, 0.0));
+    // a single state is held, before and after it
+    assert_eq!(buffer.sample(0.5, 0.25).unwrap().translation, Vec3::ZERO);
+    assert_eq!(buffer.sample(2.0, 0.25).unwrap().translation, Vec3::ZERO);
+
+    // out of order updates are sorted in
+    buffer.push(stamp(2.0), Transform::from_xyz(2.0, 0.0, 0.0));
+    buffer.push(stamp(1.5), Transform::from_xyz(1.0, 0.0, 0.0));
+    assert_eq!(buffer.sample(0.0, 0.25).unwrap().translation, Vec3::ZERO);
+    assert_eq!(buffer.sample(1.25, 0.25).unwrap().translation, Vec3::new(0.5, 0.0, 0.0));
+    assert_eq!(buffer.sample(1.75, 0.25).unwrap().translation, Vec3::new(1.5, 0.0, 0.0));
+    assert_eq!(buffer.sample(2.0, 0.25).unwrap().translation, Vec3::new(2.0, 0.0, 0.0));
+
+    // past the newest state it keeps moving along the last velocity, but only for so long
+    assert!((buffer.sample(2.1, 0.25).unwrap().translation.x - 2.2).abs() < 1e-4);
+    assert!((buffer.sample(5.0, 0.25).unwrap().translation.x - 2.5).abs() < 1e-4);
+}
+
+#[test]
+fn prediction_history_reconcile() {
+    use crate::networking::prediction::is_newer;
+    use crate::networking::PredictionHistory;
+
+    let at = |x: f32| Transform::from_xyz(x, 0.0, 0.0);
+    let mut history = PredictionHistory::default();
+    assert_eq!(history.record(at(1.0)), 0);
+    assert_eq!(history.record(at(2.0)), 1);
+    assert_eq!(history.record(at(3.0)), 2);
+    let current = at(3.0);
+
+    // the server agreed, nothing to correct
+    assert!(history.reconcile(0, &at(1.0), &current).is_none());
+    // the server ended up half a meter short, later changes are replayed from there
+    let corrected = history.reconcile(1, &at(1.5), &current).unwrap();
+    assert_eq!(corrected.translation, Vec3::new(2.5, 0.0, 0.0));
+    // the replayed prediction for 2 now matches what the server will say
+    assert!(history.reconcile(2, &at(2.5), &corrected).is_none());
+    // acks for changes that were already reconciled are ignored
+    assert!(history.reconcile(1, &at(0.0), &corrected).is_none());
+
+    // a flattened model doesn't turn the replay into infinities
+    let mut history = PredictionHistory::default();
+    let flat = Transform::from_scale(Vec3::new(0.0, 1.0, 1.0));
+    let seq = history.record(flat);
+    let corrected = history
+        .reconcile(seq, &Transform::from_scale(Vec3::ONE), &Transform::from_scale(Vec3::new(0.5, 1.0, 1.0)))
+        .unwrap();
+    assert!(corrected.scale.is_finite());
+    assert_eq!(corrected.scale, Vec3::new(1.5, 1.0, 1.0));
+
+    assert!(is_newer(1, 0));
+    assert!(!is_newer(0, 1));
+    assert!(!is_newer(5, 5));
+    assert!(is_newer(0, u32::MAX));
+}
+
+#[test]
+fn server_drops_older_changes() {
+    use crate::networking::model_server::accept_seq;
+    use crate::networking::ownership_server::transfer_ownership;
+    use crate::networking::Owner;
+    use bevy_ecs::prelude::World;
+    use leknet::{EntityMap, NetId};
+
+    let mut world = World::new();
+    world.insert_resource(EntityMap(bimap::BiHashMap::new()));
+    let entity = world.spawn((NetId(1), Networked, Owner(1))).id();
+    world.resource_mut::<EntityMap>().insert(entity, NetId(1));
+
+    assert!(accept_seq(&mut world, 1, NetId(1), 5));
+    assert!(!accept_seq(&mut world, 1, NetId(1), 4));
+    assert!(!accept_seq(&mut world, 1, NetId(1), 5));
+    assert!(accept_seq(&mut world, 1, NetId(1), 6));
+    // only the owner's changes count
+    assert!(!accept_seq(&mut world, 2, NetId(1), 7));
+    // a new owner starts its own sequence
+    transfer_ownership(&mut world, 2, NetId(1)).unwrap();
+    assert!(accept_seq(&mut world, 2, NetId(1), 0));
+}
+
+#[test]
+fn delta_baselines_survive_lost_and_late_acks() {
+    use crate::networking::delta::{DeltaBaselines, ReceivedStates};
+    use crate::networking::{ModelData2, QuantizationSettings, TransformEncoding};
+    use leknet::NetId;
+
+    let at = |x: f32| ModelData2 {
+        transform: Transform::from_xyz(x, 0.0, 0.0),
+        color128: stereokit::named_colors::AQUAMARINE,
+        render_layer: Default::default(),
+    };
+    let settings = QuantizationSettings::default();
+    let mut baselines = DeltaBaselines::default();
+    let mut received = ReceivedStates::default();
+    let net_id = NetId(1);
+
+    let (seq, baseline, delta) = baselines.encode(1, net_id, &at(0.0), TransformEncoding::Raw, &settings);
+    assert_eq!((seq, baseline), (0, None));
+    received.decode(net_id, seq, baseline, &delta, &settings).unwrap();
+    baselines.ack(1, net_id, 0);
+
+    // 1 never arrives, 2 is still relative to the acked 0 so the client can decode it
+    let (seq, baseline, _) = baselines.encode(1, net_id, &at(1.0), TransformEncoding::Raw, &settings);
+    assert_eq!((seq, baseline), (1, Some(0)));
+    let (seq, baseline, delta) = baselines.encode(1, net_id, &at(2.0), TransformEncoding::Raw, &settings);
+    assert_eq!((seq, baseline), (2, Some(0)));
+    let decoded = received.decode(net_id, seq, baseline, &delta, &settings).unwrap();
+    assert_eq!(decoded.transform.translation.x, 2.0);
+
+    // the ack for 2 overtakes the one for 1, the late one doesn't move the baseline back
+    baselines.ack(1, net_id, 2);
+    baselines.ack(1, net_id, 1);
+    let (seq, baseline, delta) = baselines.encode(1, net_id, &at(3.0), TransformEncoding::Raw, &settings);
+    assert_eq!((seq, baseline), (3, Some(2)));
+    assert_eq!(received.decode(net_id, seq, baseline, &delta, &settings).unwrap().transform.translation.x, 3.0);
+    // other clients have their own baselines
+    assert_eq!(baselines.encode(2, net_id, &at(3.0), TransformEncoding::Raw, &settings).1, None);
+
+    // after leaving a client's interest it gets a full state, without reusing sequence numbers
+    baselines.reset(1, net_id);
+    baselines.ack(1, net_id, 3);
+    assert_eq!(baselines.encode(1, net_id, &at(4.0), TransformEncoding::Raw, &settings).0, 4);
+    assert_eq!(baselines.encode(1, net_id, &at(4.0), TransformEncoding::Raw, &settings).1, None);
+    // a despawned entity is forgotten for everyone
+    baselines.forget(net_id);
+    let (seq, baseline, _) = baselines.encode(1, net_id, &at(5.0), TransformEncoding::Raw, &settings);
+    assert_eq!((seq, baseline), (0, None));
+}
+
+#[test]
+fn compact_model_deltas() {
+    use crate::networking::delta::{DeltaBaselines, ReceivedStates};
+    use crate::networking::{ModelData2, QuantizationSettings, TransformEncoding};
+    use leknet::NetId;
+
+    let at = |x: f32| ModelData2 {
+        transform: Transform::from_xyz(x, 1.0, 2.0).with_rotation(glam::Quat::from_rotation_y(0.3)),
+        color128: stereokit::named_colors::AQUAMARINE,
+        render_layer: Default::default(),
+    };
+    let settings = QuantizationSettings::default();
+    let mut baselines = DeltaBaselines::default();
+    let mut received = ReceivedStates::default();
+    let net_id = NetId(1);
+
+    let (seq, baseline, compact) = baselines.encode(1, net_id, &at(0.5), TransformEncoding::Compact, &settings);
+    let (_, _, raw) = baselines.encode(2, net_id, &at(0.5), TransformEncoding::Raw, &settings);
+    assert!(bincode::serialize(&compact).unwrap().len() < bincode::serialize(&raw).unwrap().len());
+    let compact: crate::networking::ModelDelta = bincode::deserialize(&bincode::serialize(&compact).unwrap()).unwrap();
+    let decoded = received.decode(net_id, seq, baseline, &compact, &settings).unwrap();
+    assert!(decoded.transform.translation.abs_diff_eq(at(0.5).transform.translation, settings.precision().max_element()));
+    baselines.ack(1, net_id, seq);
+
+    // movement below the quantization step isn't sent again
+    let jitter = settings.precision().x / 4.0;
+    let (seq, baseline, delta) = baselines.encode(1, net_id, &at(0.5 + jitter), TransformEncoding::Compact, &settings);
+    assert_eq!(bincode::serialize(&delta).unwrap().len(), 1);
+    let decoded2 = received.decode(net_id, seq, baseline, &delta, &settings).unwrap();
+    assert_eq!(decoded2.transform, decoded.transform);
+
+    let (seq, baseline, delta) = baselines.encode(1, net_id, &at(3.0), TransformEncoding::Compact, &settings);
+    let decoded3 = received.decode(net_id, seq, baseline, &delta, &settings).unwrap();
+    assert!((decoded3.transform.translation.x - 3.0).abs() <= settings.precision().x);
+}
+
+#[test]
+fn listen_server_runs_as_a_sub_app() {
+    use crate::networking::validation::Validators;
+    use crate::networking::{ListenServer, ModelMsgServer, ValidateAppExt, Validation};
+    use leknet::ServerBindAddr;
+
+    let mut app = bevy_app::App::new();
+    app.add_plugin(ListenServer);
+    assert!(!leknet::is_server(&app));
+    app.add_validator(|_: &bevy_ecs::prelude::World, _, _: &mut ModelMsgServer| Validation::Accept);
+    // validators added to the client app guard the server it hosts
+    assert!(!app.world.contains_resource::<Validators<ModelMsgServer>>());
+    let server_app = leknet::listen_server_mut(&mut app).expect("no listen server");
+    assert!(leknet::is_server(server_app));
+    assert!(server_app.world.contains_resource::<Validators<ModelMsgServer>>());
+    // other machines have to be able to join the host
+    let bind_addr = server_app.world.get_resource::<ServerBindAddr>().copied().unwrap_or_default();
+    assert!(bind_addr.0.ip().is_unspecified());
+}
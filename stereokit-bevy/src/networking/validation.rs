@@ -0,0 +1,271 @@
+use crate::networking::component_client::ComponentMsgClient;
+use crate::networking::hierarchy_client::HierarchyMsgClient;
+use crate::networking::interpolation::ServerStamp;
+use crate::networking::model_client::ModelMsgClient;
+use crate::networking::model_server::ModelMsgServer;
+use crate::networking::player_client::PlayerMsgClient;
+use crate::networking::player_server::PlayerMsgServer;
+use crate::networking::quantize::{from_wire, model_from_wire, model_to_wire, to_wire, WireTransform};
+use crate::networking::replication::ReplicationRegistry;
+use crate::networking::{ModelData2, Player};
+use crate::ModelInfo;
+use bevy_app::App;
+use bevy_ecs::prelude::{Resource, World};
+use bevy_hierarchy::Parent;
+use bevy_quinnet::server::Server;
+use bevy_quinnet::shared::ClientId;
+use bevy_transform::prelude::Transform;
+use leknet::{EntityMap, LekServer, NetId};
+use std::any::type_name;
+use stereokit::{Color128, RenderLayer};
+
+/// what a validator decided about an incoming message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Validation {
+    Accept,
+    /// the validator changed the message so it's acceptable, the sender is told the result
+    Clamped,
+    /// the message is dropped and the sender is sent the server's state instead
+    Reject,
+}
+
+type Validator<M> = Box<dyn Fn(&World, ClientId, &mut M) -> Validation + Send + Sync>;
+
+#[derive(Resource)]
+pub(crate) struct Validators<M>(Vec<Validator<M>>);
+
+pub trait ValidateAppExt {
+    /// runs `validator` on every `M` a client sends, before the server applies or relays it
+    fn add_validator<M>(
+        &mut self,
+        validator: impl Fn(&World, ClientId, &mut M) -> Validation + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        M: Send + Sync + 'static;
+}
+
+impl ValidateAppExt for App {
+    fn add_validator<M>(
+        &mut self,
+        validator: impl Fn(&World, ClientId, &mut M) -> Validation + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        M: Send + Sync + 'static,
+    {
+        if let Some(server_app) = leknet::listen_server_mut(self) {
+            server_app.add_validator(validator);
+            return self;
+        }
+        if !self.world.contains_resource::<Validators<M>>() {
+            self.insert_resource(Validators::<M>(vec![]));
+        }
+        self.world
+            .resource_mut::<Validators<M>>()
+            .0
+            .push(Box::new(validator));
+        self
+    }
+}
+
+/// runs every validator registered for `M`, the first rejection wins
+pub(crate) fn validate<M: Send + Sync + 'static>(
+    world: &World,
+    client_id: ClientId,
+    msg: &mut M,
+) -> Validation {
+    let validators = match world.get_resource::<Validators<M>>() {
+        None => return Validation::Accept,
+        Some(validators) => validators,
+    };
+    let mut validation = Validation::Accept;
+    for validator in validators.0.iter() {
+        match validator(world, client_id, msg) {
+            Validation::Accept => {}
+            Validation::Clamped => validation = Validation::Clamped,
+            Validation::Reject => {
+                println!("rejected {} from client {}", type_name::<M>(), client_id);
+                return Validation::Reject;
+            }
+        }
+    }
+    validation
+}
+
+/// sends the sender what the server has for `net_id`, overwriting whatever it tried to change
+pub(crate) fn send_correction(world: &mut World, client_id: ClientId, net_id: NetId) {
+    let entity = match world.resource::<EntityMap>().get_by_right(&net_id) {
+        None => return,
+        Some(entity) => *entity,
+    };
+    let stamp = ServerStamp::now(world);
+    let world_entity = match world.get_entity(entity) {
+        None => return,
+        Some(world_entity) => world_entity,
+    };
+    let player_changed = match (world_entity.contains::<Player>(), world_entity.get::<Transform>()) {
+        (true, Some(transform)) => Some(PlayerMsgClient::PlayerChanged(net_id, to_wire(world, transform), stamp)),
+        _ => None,
+    };
+    let model_changed = match (
+        world_entity.get::<Transform>(),
+        world_entity.get::<Color128>(),
+        world_entity.get::<RenderLayer>(),
+    ) {
+        (Some(transform), Some(color128), Some(render_layer)) => Some(ModelMsgClient::ModelChanged(
+            net_id,
+            model_to_wire(
+                world,
+                &ModelData2 {
+                    transform: *transform,
+                    color128: *color128,
+                    render_layer: *render_layer,
+                },
+            ),
+            stamp,
+        )),
+        _ => None,
+    };
+    let parent = world_entity
+        .get::<Parent>()
+        .and_then(|parent| world.resource::<EntityMap>().get_by_left(&parent.get()).copied());
+    let components: Vec<ComponentMsgClient> = world
+        .resource::<ReplicationRegistry>()
+        .iter()
+        .map(|(name, replicated)| match (replicated.snapshot)(world, entity) {
+            Some(bytes) => ComponentMsgClient::Changed(net_id, name.clone(), bytes),
+            None => ComponentMsgClient::Removed(net_id, name.clone()),
+        })
+        .collect();
+
+    let mut server = world.resource_mut::<Server>();
+    let endpoint = match server.get_endpoint_mut() {
+        None => return,
+        Some(endpoint) => endpoint,
+    };
+    if let Some(player_changed) = player_changed {
+        endpoint.send_lek_msg(client_id, player_changed).unwrap();
+    } else if let Some(model_changed) = model_changed {
+        endpoint.send_lek_msg(client_id, model_changed).unwrap();
+    }
+    for component in components {
+        endpoint.send_lek_msg(client_id, component).unwrap();
+    }
+    endpoint
+        .send_lek_msg(client_id, HierarchyMsgClient::ParentChanged(net_id, parent))
+        .unwrap();
+}
+
+/// limits used by the validators every server starts with
+#[derive(Resource, Clone, Debug)]
+pub struct ValidationLimits {
+    /// the furthest a player may move between two updates, in meters
+    pub max_player_step: f32,
+    /// the largest cube a client may spawn, in meters along each axis
+    pub max_model_size: f32,
+}
+
+impl Default for ValidationLimits {
+    fn default() -> Self {
+        Self {
+            max_player_step: 10.0,
+            max_model_size: 10.0,
+        }
+    }
+}
+
+pub(crate) fn is_finite(transform: &Transform) -> bool {
+    transform.translation.is_finite()
+        && transform.rotation.is_finite()
+        && transform.scale.is_finite()
+}
+
+/// whether every axis of `scale` is positive, mirrored and collapsed models are refused
+pub(crate) fn has_positive_scale(transform: &Transform) -> bool {
+    transform.scale.min_element() > 0.0
+}
+
+pub(crate) fn validate_model_msg(
+    world: &World,
+    _client_id: ClientId,
+    msg: &mut ModelMsgServer,
+) -> Validation {
+    let max_model_size = world
+        .get_resource::<ValidationLimits>()
+        .map_or(f32::INFINITY, |limits| limits.max_model_size);
+    match msg {
+        ModelMsgServer::ModelAdded(_, model_data) => {
+            if !is_finite(&model_data.transform) || !has_positive_scale(&model_data.transform) {
+                return Validation::Reject;
+            }
+            let scale = model_data.transform.scale;
+            match &mut model_data.model_info {
+                ModelInfo::Cube(size) => {
+                    if !size.is_finite() || size.min_element() <= 0.0 {
+                        return Validation::Reject;
+                    }
+                    // the limit applies to the cube as drawn, after scaling
+                    if (*size * scale).max_element() > max_model_size {
+                        *size = size.min(glam::Vec3::splat(max_model_size) / scale);
+                        return Validation::Clamped;
+                    }
+                    Validation::Accept
+                }
+                // clients can't build models from memory yet and the data is unbounded
+                ModelInfo::Mem { .. } => Validation::Reject,
+            }
+        }
+        ModelMsgServer::ModelChanged(net_id, wire_model_data, _) => {
+            let mut transform = model_from_wire(world, wire_model_data).transform;
+            if !is_finite(&transform) || !has_positive_scale(&transform) {
+                return Validation::Reject;
+            }
+            let model_info = world
+                .resource::<EntityMap>()
+                .get_by_right(net_id)
+                .and_then(|entity| world.get::<ModelInfo>(*entity));
+            match model_info {
+                Some(ModelInfo::Cube(size)) if (*size * transform.scale).max_element() > max_model_size => {
+                    transform.scale = transform.scale.min(glam::Vec3::splat(max_model_size) / *size);
+                    wire_model_data.transform = WireTransform::Raw(transform);
+                    Validation::Clamped
+                }
+                _ => Validation::Accept,
+            }
+        }
+        ModelMsgServer::DeltaAck(_, _) => Validation::Accept,
+        ModelMsgServer::ResendFull(_) => Validation::Accept,
+    }
+}
+
+pub(crate) fn validate_player_msg(
+    world: &World,
+    _client_id: ClientId,
+    msg: &mut PlayerMsgServer,
+) -> Validation {
+    match msg {
+        PlayerMsgServer::PlayerAdded(_, transform) => match is_finite(transform) {
+            true => Validation::Accept,
+            false => Validation::Reject,
+        },
+        PlayerMsgServer::PlayerChanged(net_id, wire_transform) => {
+            let transform = from_wire(world, wire_transform);
+            if !is_finite(&transform) {
+                return Validation::Reject;
+            }
+            let max_player_step = match world.get_resource::<ValidationLimits>() {
+                None => return Validation::Accept,
+                Some(limits) => limits.max_player_step,
+            };
+            let current = world
+                .resource::<EntityMap>()
+                .get_by_right(net_id)
+                .and_then(|entity| world.get::<Transform>(*entity));
+            match current {
+                Some(current) if current.translation.distance(transform.translation) > max_player_step => {
+                    Validation::Reject
+                }
+                _ => Validation::Accept,
+            }
+        }
+    }
+}
@@ -1,12 +1,13 @@
 use bevy_app::{App, Plugin};
 use bevy_ecs::component::Component;
-use bevy_ecs::prelude::{Entity, NonSend, Query, Res, With, Without};
+use bevy_ecs::prelude::{Added, Entity, EventWriter, NonSend, Query, RemovedComponents, Res, With, Without};
 use bevy_ecs::system::Commands;
 use bevy_transform::prelude::{GlobalTransform, Transform};
 use glam::Vec3;
 use serde::{Deserialize, Serialize};
 use stereokit::{bounds_point_contains, bounds_transform, Handed, Material, Mesh, Model, Sk, SkDraw, StereoKitMultiThread};
-use stereokit_bevy::networking::StereoKitBevyClientPlugins;
+use leknet::Networked;
+use stereokit_bevy::networking::{Authority, ReleaseAuthority, RequestAuthority, StereoKitBevyClientPlugins};
 use stereokit_bevy::{ModelBundle, ModelInfo};
 
 #[derive(Component, Copy, Clone, Debug, Serialize, Deserialize)]
@@ -18,6 +19,8 @@ impl Plugin for StereoKitInteraction {
     fn build(&self, app: &mut App) {
         app.add_system(hand_grab_interaction);
         app.add_system(hand_grab_interaction_2);
+        app.add_system(grab_authority);
+        app.add_system(release_authority);
     }
 }
 
@@ -97,3 +100,33 @@ fn hand_grab_interaction(
         }
     }
 }
+
+/// authority that was requested because of a grab, and is given back when the grab ends
+#[derive(Component)]
+struct GrabAuthority;
+
+/// grabbing a networked entity takes authority over it so the movement is shared
+fn grab_authority(
+    query: Query<Entity, (Added<TransformDifference>, With<Networked>, Without<Authority>)>,
+    mut requests: EventWriter<RequestAuthority>,
+    mut commands: Commands,
+) {
+    for entity in query.iter() {
+        requests.send(RequestAuthority(entity));
+        commands.entity(entity).insert(GrabAuthority);
+    }
+}
+
+fn release_authority(
+    mut removed: RemovedComponents<TransformDifference>,
+    grabbed: Query<(), With<GrabAuthority>>,
+    mut releases: EventWriter<ReleaseAuthority>,
+    mut commands: Commands,
+) {
+    for entity in removed.iter() {
+        if grabbed.contains(entity) {
+            releases.send(ReleaseAuthority(entity));
+            commands.entity(entity).remove::<GrabAuthority>();
+        }
+    }
+}
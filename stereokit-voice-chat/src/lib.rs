@@ -9,13 +9,13 @@ use bevy_quinnet::shared::channel::{ChannelId, ChannelType};
 use bevy_quinnet::shared::ClientId;
 use bevy_transform::prelude::{GlobalTransform, Transform};
 use glam::Vec3;
-use leknet::{connect_to_server, start_server, LekClient, LekServer, ClientMessageMap, ClientMessage, ServerEntity, EntityMap, TypeName, ClientEntity, ServerMessage};
+use leknet::{connect_to_server, AdmittedClients, start_server, LekClient, LekServer, ClientMessageMap, ClientMessage, NetId, EntityMap, TypeName, ServerMessage};
 use opus::{Application, Channels, Decoder, Encoder};
 use serde::{Deserialize, Serialize, Serializer};
 use std::ops::{Deref, DerefMut};
 use bevy_transform::TransformBundle;
 use stereokit::{Material, Mesh, Sk, SkDraw, Sound, SoundInstance, StereoKitMultiThread};
-use stereokit_bevy::networking::{Player, StereoKitBevyClientPlugins, StereoKitBevyServerPlugins};
+use stereokit_bevy::networking::{is_muted, is_permitted, is_relevant, ClientInterest, Permission, Player, StereoKitBevyClientPlugins, StereoKitBevyServerPlugins};
 use stereokit_bevy::{ModelBundle, ModelInfo};
 use stereokit_bevy::networking::player_client::LocalPlayer;
 
@@ -47,7 +47,7 @@ fn stereokit_audio_send(
         );
     }
     for (e, _) in player.iter() {
-        let player = entity_map.get_by_left(&ClientEntity(e.clone()));
+        let player = entity_map.get_by_left(&e);
         let player = match player {
             None => return,
             Some(player) => player,
@@ -96,7 +96,7 @@ impl DerefMut for MicrophoneDecoder {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct VoiceMessage{
-    player: ServerEntity,
+    player: NetId,
     voice_message: Vec<Vec<u8>>
 }
 
@@ -108,10 +108,16 @@ impl TypeName for VoiceMessage {
 
 impl ServerMessage for VoiceMessage {
     fn server(self, world: &mut World, client_id: ClientId) {
-        let mut server = world.get_resource_mut::<Server>().unwrap();
+        if is_muted(world, client_id)
+            || !is_permitted(world, client_id, Permission::Voice)
+        {
+            return;
+        }
+        let mut system_state: SystemState<(ResMut<Server>, Option<Res<ClientInterest>>, Res<AdmittedClients>)> = SystemState::new(world);
+        let (mut server, interest, admitted) = system_state.get_mut(world);
         let endpoint = server.endpoint_mut();
-        for client in endpoint.clients() {
-            if client == client_id {
+        for client in admitted.of(endpoint) {
+            if client == client_id || !is_relevant(interest.as_deref(), client, self.player) {
                continue;
             }
             endpoint.send_lek_msg(client, self.clone()).unwrap();
@@ -144,7 +150,7 @@ impl ClientMessage for VoiceMessage {
         let (sk, mut microphone_decoder, query, query2) = system_state.get_mut(world);
         let sk: NonSend<SkDraw> = sk;
         for (entity, children) in query.iter() {
-            if entity == client_entity.0 {
+            if entity == client_entity {
                 for child in children.iter() {
                     if let Ok(sound) = query2.get(*child) {
                         for audio in self.voice_message {